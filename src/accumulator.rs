@@ -0,0 +1,68 @@
+use crate::sample::{Kind, Sample, Samples, Statistics};
+
+pub const MAX_DISTRIBUTION_VALUES: usize = 150;
+
+struct Series {
+  distribution: Vec<(f64, f64)>,
+  latest: Sample,
+  statistics: Statistics,
+}
+
+pub struct Accumulator {
+  distribution: bool,
+  series: Vec<Series>,
+}
+
+impl Accumulator {
+  pub fn new(distribution: bool) -> Accumulator {
+    Accumulator { distribution, series: Vec::new() }
+  }
+
+  pub fn add(&mut self, samples: &[Sample]) {
+    for sample in samples.iter().filter(|s| s.value.is_finite()) {
+      let statistics = Statistics::of(sample);
+      match self.series.iter_mut().find(|s| same_series(&s.latest, sample)) {
+        Some(series) => {
+          series.statistics = series.statistics.merge(&statistics);
+          series.latest = sample.clone();
+          if self.distribution {
+            record(&mut series.distribution, sample.value);
+          }
+        },
+        None => self.series.push(Series {
+          distribution: if self.distribution { vec![(sample.value, 1.0)] } else { Vec::new() },
+          latest: sample.clone(),
+          statistics,
+        }),
+      }
+    }
+  }
+
+  pub fn report(&mut self) -> Samples {
+    let distribution = self.distribution;
+    self.series.drain(..).map(|series| Sample {
+      distribution: if distribution { Some(series.distribution) } else { None },
+      statistics: Some(series.statistics),
+      value: match series.latest.kind {
+        Kind::Gauge => series.statistics.average(),
+        Kind::Sum => series.statistics.sum,
+        Kind::Cumulative => series.latest.value,
+      },
+      ..series.latest
+    }).collect()
+  }
+}
+
+fn same_series(a: &Sample, b: &Sample) -> bool {
+  a.name == b.name && a.unit == b.unit && a.kind == b.kind && a.dimensions == b.dimensions && a.resource == b.resource
+}
+
+fn record(distribution: &mut Vec<(f64, f64)>, value: f64) {
+  if let Some(bucket) = distribution.iter_mut().find(|(v, _)| *v == value) {
+    bucket.1 += 1.0;
+  } else if distribution.len() < MAX_DISTRIBUTION_VALUES {
+    distribution.push((value, 1.0));
+  } else if let Some(nearest) = distribution.iter_mut().min_by(|(a, _), (b, _)| (a - value).abs().total_cmp(&(b - value).abs())) {
+    nearest.1 += 1.0;
+  }
+}
@@ -0,0 +1,76 @@
+use failure::{Error, format_err};
+use rusoto_cloudwatch::CloudWatchClient;
+use rusoto_core::{DefaultCredentialsProvider, HttpClient, Region};
+use rusoto_core::credential::{AutoRefreshingProvider, ContainerProvider, EnvironmentProvider};
+use rusoto_sts::{StsAssumeRoleSessionCredentialsProvider, StsClient};
+use std::str::FromStr;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum CredentialSource {
+  #[default]
+  Default,
+  Container,
+  Environment,
+  AssumeRole,
+}
+
+impl FromStr for CredentialSource {
+  type Err = Error;
+
+  fn from_str(source: &str) -> Result<CredentialSource, Error> {
+    match source {
+      "default" => Ok(CredentialSource::Default),
+      "container" => Ok(CredentialSource::Container),
+      "env" => Ok(CredentialSource::Environment),
+      "assume-role" => Ok(CredentialSource::AssumeRole),
+      _ => Err(format_err!("Unknown credentials source {}", source)),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AwsSettings {
+  pub credentials: CredentialSource,
+  pub endpoint: Option<String>,
+  pub external_id: Option<String>,
+  pub region: Option<String>,
+  pub role_arn: Option<String>,
+  pub session_name: String,
+}
+
+pub fn region(name: Option<&str>, endpoint: Option<&str>) -> Result<Region, Error> {
+  let region = match name {
+    Some(name) => name.parse().map_err(|_| format_err!("Unknown AWS region {}", name))?,
+    None => Region::default(),
+  };
+  Ok(match endpoint {
+    Some(endpoint) => Region::Custom { name: String::from(region.name()), endpoint: String::from(endpoint) },
+    None => region,
+  })
+}
+
+pub fn cloudwatch_client(settings: &AwsSettings) -> Result<CloudWatchClient, Error> {
+  let region = region(settings.region.as_deref(), settings.endpoint.as_deref())?;
+  Ok(match settings.credentials {
+    CredentialSource::Default =>
+      CloudWatchClient::new_with(HttpClient::new()?, DefaultCredentialsProvider::new()?, region),
+    CredentialSource::Container =>
+      CloudWatchClient::new_with(HttpClient::new()?, AutoRefreshingProvider::new(ContainerProvider::new())?, region),
+    CredentialSource::Environment =>
+      CloudWatchClient::new_with(HttpClient::new()?, EnvironmentProvider, region),
+    CredentialSource::AssumeRole => {
+      let role_arn = settings.role_arn.clone().ok_or_else(|| format_err!("Assuming a role requires a role ARN"))?;
+      let sts = StsClient::new(self::region(settings.region.as_deref(), None)?);
+      let provider = StsAssumeRoleSessionCredentialsProvider::new(
+        sts,
+        role_arn,
+        settings.session_name.clone(),
+        settings.external_id.clone(),
+        None,
+        None,
+        None
+      );
+      CloudWatchClient::new_with(HttpClient::new()?, AutoRefreshingProvider::new(provider)?, region)
+    },
+  })
+}
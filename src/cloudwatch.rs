@@ -1,66 +1,304 @@
-use crate::metadata_v2;
-use failure::{Error, format_err};
-use log::warn;
-use rusoto_cloudwatch::{CloudWatch, MetricDatum, PutMetricDataError, PutMetricDataInput};
-use std::collections::HashMap;
-
-pub type Metrics = Vec<MetricDatum>;
-
-pub fn metrics_from_stats(metrics: &mut Metrics, stats: Vec<metadata_v2::Stats>, metadata: &HashMap<String, metadata_v2::Metadata>) {
-  stats.into_iter()
-    .filter(|s| metadata.contains_key(&s.container_id))
-    .flat_map(|s| {
-      let dimensions = &metadata.get(&s.container_id).unwrap().dimensions;
-      let timestamp = s.timestamp;
-      s.metrics.into_iter().map(move |m|
-        MetricDatum {
-          dimensions: Some(dimensions.clone()),
-          metric_name: m.name,
-          timestamp: Some(format!("{}", timestamp.format("%FT%T%.3f%:z"))),
-          unit: Some(m.unit),
-          value: Some(m.value),
-          ..Default::default()
-        }
-      )
-    })
-    .for_each(|m| metrics.push(m));
-}
-
-pub fn report_to_cloudwatch(client: &impl CloudWatch, namespace: &str, data: &Metrics) -> Result<usize, Error> {
-  let mut start_index = 0;
-  while data.len() > start_index {
-    let chunk: Metrics = data.iter().skip(start_index).take(20).map(|l| l.clone()).collect();
-    let chunk_size = chunk.len();
-    match client.put_metric_data(PutMetricDataInput {
+use crate::retry::{Backoff, RetryBudget};
+use crate::sample::Sample;
+use chrono::Timelike;
+use crate::sink::{Action, Sink};
+use failure::format_err;
+use log::debug;
+use rusoto_cloudwatch::{CloudWatch, Dimension, MetricDatum, PutMetricDataError, PutMetricDataInput, StatisticSet};
+use std::cmp::min;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+pub const MAX_DATUMS_PER_REQUEST: usize = 1000;
+pub const MAX_BYTES_PER_REQUEST: usize = 1_000_000;
+const THROTTLING_CODES: [&str; 4] = ["Throttling", "ThrottlingException", "RequestLimitExceeded", "TooManyRequestsException"];
+const RETRY_COST: f64 = 1.0;
+const THROTTLED_RETRY_COST: f64 = 2.0;
+const SUCCESS_DEPOSIT: f64 = 0.2;
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_DIMENSION_VALUE_LENGTH: usize = 1024;
+const MAX_DIMENSIONS: usize = 30;
+const VALUE_RANGE_EXPONENT: i32 = 360;
+const HIGH_RESOLUTION: i64 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchLimits {
+  pub max_datums: usize,
+  pub max_bytes: usize,
+}
+
+impl Default for BatchLimits {
+  fn default() -> BatchLimits {
+    BatchLimits { max_datums: MAX_DATUMS_PER_REQUEST, max_bytes: MAX_BYTES_PER_REQUEST }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+  pub retries: u32,
+  pub backoff: Backoff,
+  pub budget: usize,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> RetryPolicy {
+    RetryPolicy {
+      retries: 2,
+      backoff: Backoff { initial_delay: Duration::from_millis(100), max_delay: Duration::from_secs(5) },
+      budget: 20,
+    }
+  }
+}
+
+pub struct CloudWatchSink<C: CloudWatch> {
+  budget: RetryBudget,
+  client: C,
+  high_resolution: Vec<String>,
+  limits: BatchLimits,
+  namespace: String,
+  retry: RetryPolicy,
+}
+
+impl<C: CloudWatch> CloudWatchSink<C> {
+  pub fn new(client: C, namespace: &str) -> CloudWatchSink<C> {
+    CloudWatchSink::with_limits(client, namespace, BatchLimits::default())
+  }
+
+  pub fn with_limits(client: C, namespace: &str, limits: BatchLimits) -> CloudWatchSink<C> {
+    CloudWatchSink {
+      budget: RetryBudget::new(0),
+      client,
+      high_resolution: Vec::new(),
+      limits,
       namespace: String::from(namespace),
-      metric_data: chunk,
-    }).sync() {
-      Ok(_) => {
-        start_index += chunk_size;
-        Ok(())
-      },
-      Err(err) => match classify_cloudwatch_error(err) {
-        Action::Retry(cause) => {
-          warn!("Retrying error {}", cause);
-          break
+      retry: RetryPolicy { retries: 0, ..Default::default() },
+    }
+  }
+
+  pub fn with_retry(mut self, retry: RetryPolicy) -> CloudWatchSink<C> {
+    self.budget = RetryBudget::new(retry.budget);
+    self.retry = retry;
+    self
+  }
+
+  pub fn with_high_resolution(mut self, metrics: &[String]) -> CloudWatchSink<C> {
+    self.high_resolution = metrics.to_vec();
+    self
+  }
+
+  pub fn retry_budget(&self) -> f64 {
+    self.budget.tokens()
+  }
+
+  pub fn datum(&self, sample: &Sample) -> MetricDatum {
+    if self.high_resolution.contains(&sample.name) {
+      high_resolution_datum_from_sample(sample)
+    } else {
+      datum_from_sample(sample)
+    }
+  }
+
+  fn put(&self, batch: &[Sample]) -> Result<(), PutMetricDataError> {
+    self.client.put_metric_data(PutMetricDataInput {
+      namespace: self.namespace.clone(),
+      metric_data: batch.iter().map(|sample| self.datum(sample)).collect(),
+    }).sync()
+  }
+}
+
+impl<C: CloudWatch> Sink for CloudWatchSink<C> {
+  fn name(&self) -> &str {
+    "cloudwatch"
+  }
+
+  fn batch_len(&self, pending: &[Sample]) -> usize {
+    let mut request_size = request_overhead(&self.namespace);
+    let mut count = 0;
+    for sample in pending.iter().take(self.limits.max_datums) {
+      request_size += datum_size(count + 1, &self.datum(sample));
+      if count > 0 && request_size > self.limits.max_bytes {
+        break;
+      }
+      count += 1;
+    }
+    count
+  }
+
+  fn validate(&self, sample: &Sample) -> Result<(), String> {
+    validate_sample(sample)
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action> {
+    self.send_within(batch, None)
+  }
+
+  fn send_until(&mut self, batch: &[Sample], deadline: Instant) -> Result<(), Action> {
+    self.send_within(batch, Some(deadline))
+  }
+}
+
+impl<C: CloudWatch> CloudWatchSink<C> {
+  fn send_within(&mut self, batch: &[Sample], deadline: Option<Instant>) -> Result<(), Action> {
+    let mut attempt = 0;
+    let mut spent = 0.0;
+    loop {
+      let err = match self.put(batch) {
+        Ok(()) => {
+          self.budget.deposit(if spent > 0.0 { spent } else { SUCCESS_DEPOSIT });
+          return Ok(());
         },
-        Action::Fail(err) => Err(err),
+        Err(err) => err,
+      };
+      let throttled = is_throttling(&err);
+      let cause = match classify_cloudwatch_error(err) {
+        Action::Retry(cause) => cause,
+        fail => return Err(fail),
+      };
+      let cost = if throttled { THROTTLED_RETRY_COST } else { RETRY_COST };
+      if attempt >= self.retry.retries || !self.budget.withdraw(cost) {
+        return Err(Action::Retry(cause));
       }
-    }?;
+      spent += cost;
+      let mut delay = self.retry.backoff.delay(if throttled { attempt + 1 } else { attempt });
+      if let Some(deadline) = deadline {
+        delay = min(delay, deadline.saturating_duration_since(Instant::now()));
+      }
+      debug!("PutMetricData attempt {} failed, retrying in {:?}: {}", attempt + 1, delay, cause);
+      sleep(delay);
+      if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(Action::Retry(cause));
+      }
+      attempt += 1;
+    }
+  }
+}
+
+pub fn datum_from_sample(sample: &Sample) -> MetricDatum {
+  let mut datum = MetricDatum {
+    dimensions: Some(sample.dimensions.iter().map(|d| Dimension {
+      name: d.name.clone(),
+      value: d.value.clone(),
+    }).collect()),
+    metric_name: sample.name.clone(),
+    timestamp: Some(format!("{}", sample.timestamp.format("%FT%T%.3f%:z"))),
+    unit: Some(sample.unit.clone()),
+    ..Default::default()
+  };
+  match (&sample.distribution, sample.statistics) {
+    (Some(distribution), _) => {
+      datum.values = Some(distribution.iter().map(|(value, _)| *value).collect());
+      datum.counts = Some(distribution.iter().map(|(_, count)| *count).collect());
+    },
+    (None, Some(statistics)) => datum.statistic_values = Some(StatisticSet {
+      sample_count: statistics.sample_count,
+      sum: statistics.sum,
+      minimum: statistics.minimum,
+      maximum: statistics.maximum,
+    }),
+    (None, None) => datum.value = Some(sample.value),
   }
-  Ok(start_index)
+  datum
 }
 
-pub enum Action {
-  Retry(String),
-  Fail(Error),
+pub fn high_resolution_datum_from_sample(sample: &Sample) -> MetricDatum {
+  let rounded = (sample.timestamp + chrono::Duration::milliseconds(500)).with_nanosecond(0).unwrap_or(sample.timestamp);
+  MetricDatum {
+    storage_resolution: Some(HIGH_RESOLUTION),
+    timestamp: Some(format!("{}", rounded.format("%FT%T%.3f%:z"))),
+    ..datum_from_sample(sample)
+  }
+}
+
+pub fn is_throttling(error: &PutMetricDataError) -> bool {
+  match error {
+    PutMetricDataError::Unknown(response) => {
+      let body = String::from_utf8_lossy(&response.body);
+      THROTTLING_CODES.iter().any(|code| body.contains(&format!("<Code>{}</Code>", code)))
+    },
+    _ => false,
+  }
 }
 
 pub fn classify_cloudwatch_error(error: PutMetricDataError) -> Action {
+  let throttled = is_throttling(&error);
   match error {
     PutMetricDataError::HttpDispatch(err) => Action::Retry(format!("{:?}", err)),
     PutMetricDataError::InternalServiceFault(message) => Action::Retry(message),
-    PutMetricDataError::Unknown(response) => Action::Retry(String::from_utf8(response.body).unwrap()),
+    PutMetricDataError::Unknown(response) if throttled =>
+      Action::Retry(format!("Throttled by CloudWatch: {}", String::from_utf8_lossy(&response.body))),
+    PutMetricDataError::Unknown(response) => Action::Retry(String::from_utf8_lossy(&response.body).into_owned()),
+    PutMetricDataError::InvalidParameterValue(message) => Action::Reject(message),
+    PutMetricDataError::InvalidParameterCombination(message) => Action::Reject(message),
     err => Action::Fail(format_err!("{}", err)),
   }
 }
+
+pub fn validate_sample(sample: &Sample) -> Result<(), String> {
+  let magnitude = sample.value.abs();
+  if !sample.value.is_finite() {
+    return Err(format!("value {} is not finite", sample.value));
+  }
+  if magnitude > 2f64.powi(VALUE_RANGE_EXPONENT) || (magnitude != 0.0 && magnitude < 2f64.powi(-VALUE_RANGE_EXPONENT)) {
+    return Err(format!("value {} is outside the range CloudWatch accepts", sample.value));
+  }
+  if sample.name.is_empty() || sample.name.chars().count() > MAX_NAME_LENGTH {
+    return Err(format!("metric name must be 1 to {} characters", MAX_NAME_LENGTH));
+  }
+  if sample.dimensions.len() > MAX_DIMENSIONS {
+    return Err(format!("{} dimensions exceed the limit of {}", sample.dimensions.len(), MAX_DIMENSIONS));
+  }
+  for dimension in &sample.dimensions {
+    if dimension.name.is_empty() || dimension.name.chars().count() > MAX_NAME_LENGTH {
+      return Err(format!("dimension name {} must be 1 to {} characters", dimension.name, MAX_NAME_LENGTH));
+    }
+    if dimension.value.is_empty() || dimension.value.chars().count() > MAX_DIMENSION_VALUE_LENGTH {
+      return Err(format!("dimension {} value must be 1 to {} characters", dimension.name, MAX_DIMENSION_VALUE_LENGTH));
+    }
+  }
+  Ok(())
+}
+
+fn param_size(key: &str, value: &str) -> usize {
+  let encoded_value: usize = value.bytes()
+    .map(|b| if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) { 1 } else { 3 })
+    .sum();
+  key.len() + 1 + encoded_value + 1
+}
+
+pub fn request_overhead(namespace: &str) -> usize {
+  param_size("Action", "PutMetricData") + param_size("Version", "2010-08-01") + param_size("Namespace", namespace)
+}
+
+pub fn datum_size(member: usize, datum: &MetricDatum) -> usize {
+  let prefix = format!("MetricData.member.{}", member);
+  let field = |name: &str, value: &str| param_size(&format!("{}.{}", prefix, name), value);
+  let mut size = field("MetricName", &datum.metric_name);
+  if let Some(value) = datum.value {
+    size += field("Value", &value.to_string());
+  }
+  if let Some(unit) = &datum.unit {
+    size += field("Unit", unit);
+  }
+  if let Some(timestamp) = &datum.timestamp {
+    size += field("Timestamp", timestamp);
+  }
+  if let Some(resolution) = datum.storage_resolution {
+    size += field("StorageResolution", &resolution.to_string());
+  }
+  if let Some(statistics) = &datum.statistic_values {
+    size += field("StatisticValues.SampleCount", &statistics.sample_count.to_string())
+      + field("StatisticValues.Sum", &statistics.sum.to_string())
+      + field("StatisticValues.Minimum", &statistics.minimum.to_string())
+      + field("StatisticValues.Maximum", &statistics.maximum.to_string());
+  }
+  for (name, list) in &[("Values", &datum.values), ("Counts", &datum.counts)] {
+    for (i, value) in list.iter().flat_map(|values| values.iter()).enumerate() {
+      size += field(&format!("{}.member.{}", name, i + 1), &value.to_string());
+    }
+  }
+  for (i, dimension) in datum.dimensions.iter().flat_map(|dimensions| dimensions.iter()).enumerate() {
+    size += field(&format!("Dimensions.member.{}.Name", i + 1), &dimension.name)
+      + field(&format!("Dimensions.member.{}.Value", i + 1), &dimension.value);
+  }
+  size
+}
@@ -1,21 +1,66 @@
 use args::Args;
-use failure::Error;
+use crate::aws::{AwsSettings, CredentialSource};
+use crate::cloudwatch::{self, BatchLimits, RetryPolicy};
+use crate::dimension_spec::{self, DimensionSpec};
+use crate::metadata_v2::NetworkReporting;
+use crate::metric_spec::{self, MetricSpec};
+use crate::overflow::OverflowPolicy;
+use crate::sink::SinkConfig;
+use crate::spool::{self, SpoolLimits};
+use failure::{Error, format_err};
 use getopts::Occur;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
 use std::time::Duration;
 
 #[derive(Debug, Default, PartialEq)]
 pub struct Configuration {
-  pub base_url: String,
+  pub aws: AwsSettings,
+  pub base_url: Option<String>,
+  pub cloudwatch_limits: BatchLimits,
+  pub cloudwatch_retry: RetryPolicy,
+  pub dimensions: Vec<DimensionSpec>,
+  pub high_resolution: Vec<String>,
+  pub influx_bucket: Option<String>,
+  pub influx_org: Option<String>,
+  pub influx_token: Option<Secret>,
+  pub influx_url: String,
   pub interval: Duration,
   pub log_level: usize,
+  pub max_scrape_failures: usize,
+  pub metrics: Vec<MetricSpec>,
   pub namespace: String,
+  pub network_reporting: NetworkReporting,
+  pub otlp_endpoint: String,
+  pub prometheus_listen: String,
+  pub queue_overflow: OverflowPolicy,
   pub queue_size: usize,
+  pub sample_distribution: bool,
+  pub sample_interval: Option<Duration>,
+  pub scrape_retries: usize,
+  pub shutdown_deadline: Duration,
+  pub sinks: Vec<SinkConfig>,
+  pub spool_dir: Option<PathBuf>,
+  pub spool_limits: SpoolLimits,
+  pub statsd_address: String,
+  pub statsd_max_packet: usize,
+  pub statsd_prefix: String,
+}
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Secret(pub String);
+
+impl fmt::Debug for Secret {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<redacted>")
+  }
 }
 
 const PROGRAM_DESC: &'static str = "Small daemon to report selected Docker stats as Cloudwatch metrics.";
 
 pub enum RunMode {
-  Normal(Configuration),
+  Normal(Box<Configuration>),
   Help(String),
 }
 
@@ -28,13 +73,24 @@ pub fn parse_args(args: &Vec<String>) -> Result<RunMode, Error> {
     "NAMESPACE",
     Occur::Optional,
     None);
+  argparser.option(
+    "d",
+    "dimension",
+    "Add a dimension from container metadata (Name, DockerId, DockerName, Image, ImageID), task metadata \
+     (Cluster, Family, Revision, TaskARN, AvailabilityZone), derived values (TaskId, ClusterName) or a JSON \
+     pointer (container:/POINTER, task:/POINTER); defaults to task=Name (may be repeated)",
+    "NAME=SOURCE",
+    Occur::Multi,
+    None
+  );
   argparser.option(
     "e",
     "metadata-endpoint",
-    "HTTP base URL where /v2/metadata and /v2/stats can be found",
+    "HTTP base URL where /v2/metadata and /v2/stats can be found; defaults to http://169.254.170.2, \
+     or the v4 endpoint from ECS_CONTAINER_METADATA_URI_V4 when that is set",
     "BASE URL",
     Occur::Optional,
-    Some("http://169.254.170.2".to_owned())
+    None
   );
   argparser.option(
     "i",
@@ -44,6 +100,20 @@ pub fn parse_args(args: &Vec<String>) -> Result<RunMode, Error> {
     Occur::Optional,
     Some("60".to_owned())
   );
+  argparser.option(
+    "",
+    "sample-interval",
+    "Sample stats this often and report their minimum, maximum, sum and count every --interval, to catch \
+     peaks between reports",
+    "SECONDS",
+    Occur::Optional,
+    None
+  );
+  argparser.flag(
+    "",
+    "sample-distribution",
+    "With --sample-interval, report the distribution of sampled values so CloudWatch can compute percentiles"
+  );
   argparser.option(
     "l",
     "log-level",
@@ -52,14 +122,265 @@ pub fn parse_args(args: &Vec<String>) -> Result<RunMode, Error> {
     Occur::Optional,
     Some("1".to_owned())
   );
+  argparser.option(
+    "m",
+    "metric",
+    "Collect a metric from the stats document, e.g. /memory_stats/usage,usage,Bytes (may be repeated)",
+    "POINTER,NAME,UNIT[,SCALE]",
+    Occur::Multi,
+    None
+  );
+  argparser.option(
+    "",
+    "metric-spec",
+    "JSON file with a list of {pointer, name, unit, scale} metrics to collect",
+    "FILE",
+    Occur::Optional,
+    None
+  );
+  argparser.flag(
+    "",
+    "network-per-interface",
+    "Report network rates per interface rather than summed over all interfaces"
+  );
+  argparser.option(
+    "",
+    "prometheus-listen",
+    "Address where the prometheus sink serves /metrics",
+    "ADDRESS",
+    Occur::Optional,
+    Some("0.0.0.0:9102".to_owned())
+  );
   argparser.option(
     "q",
     "queue-size",
-    "Number of metric datums to keep in queue during communication outages",
+    "Number of metric datums each sink keeps in queue during communication outages",
     "QUEUE_SIZE",
     Occur::Optional,
     Some("100".to_owned())
   );
+  argparser.option(
+    "",
+    "queue-overflow",
+    "What to drop when a queue is full: drop-oldest, drop-newest, reservoir (a uniform sample of the \
+     outage), keep-extremes (each series' minimum and maximum survive) or compact (fold old metrics into \
+     per-minute statistic sets)",
+    "POLICY",
+    Occur::Optional,
+    Some("drop-oldest".to_owned())
+  );
+  argparser.option(
+    "",
+    "scrape-retries",
+    "Number of times to retry a failed scrape of the metadata endpoint within one interval",
+    "NUM",
+    Occur::Optional,
+    Some("2".to_owned())
+  );
+  argparser.option(
+    "",
+    "max-scrape-failures",
+    "Exit after this many consecutive failed scrapes (0 = never give up)",
+    "NUM",
+    Occur::Optional,
+    Some("10".to_owned())
+  );
+  argparser.option(
+    "",
+    "shutdown-deadline",
+    "Time allowed for flushing queued metrics after SIGTERM or SIGINT",
+    "SECONDS",
+    Occur::Optional,
+    Some("10".to_owned())
+  );
+  argparser.option(
+    "",
+    "spool-dir",
+    "Directory in which to spool queued metrics so they survive a restart; spooling is off when unset",
+    "DIR",
+    Occur::Optional,
+    None
+  );
+  argparser.option(
+    "",
+    "spool-max-bytes",
+    "Maximum size of each sink's spool file; the oldest metrics are dropped beyond it",
+    "BYTES",
+    Occur::Optional,
+    Some(spool::DEFAULT_MAX_BYTES.to_string())
+  );
+  argparser.option(
+    "",
+    "spool-max-age",
+    "Spooled metrics older than this are dropped instead of replayed",
+    "SECONDS",
+    Occur::Optional,
+    Some(spool::DEFAULT_MAX_AGE.as_secs().to_string())
+  );
+  argparser.option(
+    "s",
+    "sink",
+    "Where to send metrics: cloudwatch, prometheus, statsd, dogstatsd, emf, influxdb or otlp, optionally with \
+     its own queue_size and batch_size, e.g. statsd,queue_size=1000,batch_size=50 (may be repeated)",
+    "SINK[,KEY=VALUE...]",
+    Occur::Multi,
+    Some("cloudwatch".to_owned())
+  );
+  argparser.option(
+    "",
+    "statsd-address",
+    "UDP address of the StatsD or DogStatsD agent",
+    "HOST:PORT",
+    Occur::Optional,
+    Some("127.0.0.1:8125".to_owned())
+  );
+  argparser.option(
+    "",
+    "statsd-prefix",
+    "Prefix for StatsD metric names, e.g. ecs.myapp",
+    "PREFIX",
+    Occur::Optional,
+    Some("".to_owned())
+  );
+  argparser.option(
+    "",
+    "statsd-max-packet",
+    "Maximum size of a StatsD UDP packet; metrics are batched up to this size",
+    "BYTES",
+    Occur::Optional,
+    Some("1432".to_owned())
+  );
+  argparser.option(
+    "",
+    "influx-url",
+    "Base URL of the InfluxDB v2 server",
+    "URL",
+    Occur::Optional,
+    Some("http://localhost:8086".to_owned())
+  );
+  argparser.option(
+    "",
+    "influx-org",
+    "InfluxDB organization to write to",
+    "ORG",
+    Occur::Optional,
+    None
+  );
+  argparser.option(
+    "",
+    "influx-bucket",
+    "InfluxDB bucket to write to",
+    "BUCKET",
+    Occur::Optional,
+    None
+  );
+  argparser.option(
+    "",
+    "influx-token",
+    "InfluxDB API token; defaults to the INFLUX_TOKEN environment variable",
+    "TOKEN",
+    Occur::Optional,
+    None
+  );
+  argparser.option(
+    "",
+    "otlp-endpoint",
+    "Base URL of the OpenTelemetry collector's OTLP/HTTP receiver",
+    "URL",
+    Occur::Optional,
+    Some("http://localhost:4318".to_owned())
+  );
+  argparser.option(
+    "",
+    "cloudwatch-max-datums",
+    "Maximum number of metric datums in one PutMetricData request",
+    "NUM",
+    Occur::Optional,
+    Some(cloudwatch::MAX_DATUMS_PER_REQUEST.to_string())
+  );
+  argparser.option(
+    "",
+    "cloudwatch-max-bytes",
+    "Maximum estimated size of one PutMetricData request",
+    "BYTES",
+    Occur::Optional,
+    Some(cloudwatch::MAX_BYTES_PER_REQUEST.to_string())
+  );
+  argparser.option(
+    "",
+    "high-resolution",
+    "Report a metric to CloudWatch with 1-second storage resolution, e.g. usage (may be repeated); \
+     combine with an --interval or --sample-interval of 1 to 10 seconds",
+    "METRIC",
+    Occur::Multi,
+    None
+  );
+  argparser.option(
+    "",
+    "cloudwatch-retries",
+    "Number of times to retry a failed or throttled PutMetricData request within one interval",
+    "NUM",
+    Occur::Optional,
+    Some(RetryPolicy::default().retries.to_string())
+  );
+  argparser.option(
+    "",
+    "cloudwatch-retry-budget",
+    "Retry tokens available to CloudWatch retries; each retry spends one (two when throttled) \
+     and successful requests slowly earn them back",
+    "NUM",
+    Occur::Optional,
+    Some(RetryPolicy::default().budget.to_string())
+  );
+  argparser.option(
+    "",
+    "region",
+    "AWS region to report to; defaults to AWS_DEFAULT_REGION or AWS_REGION",
+    "REGION",
+    Occur::Optional,
+    None
+  );
+  argparser.option(
+    "",
+    "cloudwatch-endpoint",
+    "Custom CloudWatch endpoint URL, e.g. a VPC interface endpoint or LocalStack",
+    "URL",
+    Occur::Optional,
+    None
+  );
+  argparser.option(
+    "",
+    "credentials",
+    "Where to get AWS credentials: default (provider chain), container (task role), env (static \
+     AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY) or assume-role",
+    "SOURCE",
+    Occur::Optional,
+    Some("default".to_owned())
+  );
+  argparser.option(
+    "",
+    "role-arn",
+    "Role to assume via STS with --credentials assume-role",
+    "ARN",
+    Occur::Optional,
+    None
+  );
+  argparser.option(
+    "",
+    "external-id",
+    "External ID to present when assuming --role-arn",
+    "ID",
+    Occur::Optional,
+    None
+  );
+  argparser.option(
+    "",
+    "role-session-name",
+    "Session name to use when assuming --role-arn",
+    "NAME",
+    Occur::Optional,
+    Some("fargate-stats-reporter".to_owned())
+  );
   argparser.flag("h", "help", "Print this help and exit");
 
   argparser.parse(args)?;
@@ -67,12 +388,110 @@ pub fn parse_args(args: &Vec<String>) -> Result<RunMode, Error> {
   if argparser.value_of("help")? {
     Ok(RunMode::Help(argparser.full_usage()))
   } else {
-    Ok(RunMode::Normal(Configuration {
-      base_url: argparser.value_of("metadata-endpoint")?,
-      interval: Duration::from_secs(argparser.value_of("interval")?),
+    let interval = Duration::from_secs(argparser.value_of("interval")?);
+    if interval.as_secs() == 0 {
+      return Err(format_err!("--interval must be at least 1 second"));
+    }
+    let sample_interval = argparser.optional_value_of("sample-interval")?.map(Duration::from_secs);
+    if sample_interval.is_some_and(|sample_interval| sample_interval.as_secs() == 0 || sample_interval > interval) {
+      return Err(format_err!("--sample-interval must be between 1 second and --interval"));
+    }
+    Ok(RunMode::Normal(Box::new(Configuration {
+      aws: aws_settings(&argparser)?,
+      base_url: argparser.optional_value_of("metadata-endpoint")?,
+      cloudwatch_limits: BatchLimits {
+        max_datums: argparser.value_of("cloudwatch-max-datums")?,
+        max_bytes: argparser.value_of("cloudwatch-max-bytes")?,
+      },
+      cloudwatch_retry: RetryPolicy {
+        retries: argparser.value_of("cloudwatch-retries")?,
+        budget: argparser.value_of("cloudwatch-retry-budget")?,
+        ..Default::default()
+      },
+      dimensions: dimension_specs(&argparser)?,
+      high_resolution: argparser.optional_values_of("high-resolution")?.unwrap_or_default(),
+      influx_bucket: argparser.optional_value_of("influx-bucket")?,
+      influx_org: argparser.optional_value_of("influx-org")?,
+      influx_token: match argparser.optional_value_of("influx-token")? {
+        Some(token) => Some(token),
+        None => env::var("INFLUX_TOKEN").ok().filter(|token| !token.is_empty()),
+      }.map(Secret),
+      influx_url: argparser.value_of("influx-url")?,
+      interval,
       log_level: argparser.value_of("log-level")?,
+      max_scrape_failures: argparser.value_of("max-scrape-failures")?,
+      metrics: metric_specs(&argparser)?,
       namespace: argparser.value_of("metric-namespace")?,
+      network_reporting: if argparser.value_of("network-per-interface")? {
+        NetworkReporting::PerInterface
+      } else {
+        NetworkReporting::Summed
+      },
+      otlp_endpoint: argparser.value_of("otlp-endpoint")?,
+      prometheus_listen: argparser.value_of("prometheus-listen")?,
+      queue_overflow: argparser.value_of::<String>("queue-overflow")?.parse()?,
       queue_size: argparser.value_of("queue-size")?,
-    }))
+      sample_distribution: argparser.value_of("sample-distribution")?,
+      sample_interval,
+      scrape_retries: argparser.value_of("scrape-retries")?,
+      shutdown_deadline: Duration::from_secs(argparser.value_of("shutdown-deadline")?),
+      sinks: sink_configs(&argparser)?,
+      spool_dir: argparser.optional_value_of::<String>("spool-dir")?.map(PathBuf::from),
+      spool_limits: SpoolLimits {
+        max_bytes: argparser.value_of("spool-max-bytes")?,
+        max_age: Duration::from_secs(argparser.value_of("spool-max-age")?),
+      },
+      statsd_address: argparser.value_of("statsd-address")?,
+      statsd_max_packet: argparser.value_of("statsd-max-packet")?,
+      statsd_prefix: argparser.value_of("statsd-prefix")?,
+    })))
+  }
+}
+
+fn metric_specs(argparser: &Args) -> Result<Vec<MetricSpec>, Error> {
+  let mut specs = match argparser.optional_value_of::<String>("metric-spec")? {
+    Some(path) => metric_spec::load_specs(&path)?,
+    None => Vec::new(),
+  };
+  for spec in argparser.optional_values_of::<String>("metric")?.unwrap_or_default() {
+    specs.push(spec.parse()?);
+  }
+  if specs.is_empty() {
+    specs = metric_spec::default_specs();
+  }
+  Ok(specs)
+}
+
+fn dimension_specs(argparser: &Args) -> Result<Vec<DimensionSpec>, Error> {
+  match argparser.optional_values_of::<String>("dimension")? {
+    Some(specs) => specs.iter().map(|spec| spec.parse()).collect(),
+    None => Ok(dimension_spec::default_specs()),
+  }
+}
+
+fn sink_configs(argparser: &Args) -> Result<Vec<SinkConfig>, Error> {
+  let mut configs: Vec<SinkConfig> = Vec::new();
+  for spec in argparser.values_of::<String>("sink")? {
+    let config: SinkConfig = spec.parse()?;
+    if configs.iter().any(|c| c.kind == config.kind) {
+      return Err(format_err!("Sink {:?} is configured more than once", config.kind));
+    }
+    configs.push(config);
+  }
+  Ok(configs)
+}
+
+fn aws_settings(argparser: &Args) -> Result<AwsSettings, Error> {
+  let settings = AwsSettings {
+    credentials: argparser.value_of::<String>("credentials")?.parse()?,
+    endpoint: argparser.optional_value_of("cloudwatch-endpoint")?,
+    external_id: argparser.optional_value_of("external-id")?,
+    region: argparser.optional_value_of("region")?,
+    role_arn: argparser.optional_value_of("role-arn")?,
+    session_name: argparser.value_of("role-session-name")?,
+  };
+  if settings.credentials == CredentialSource::AssumeRole && settings.role_arn.is_none() {
+    return Err(format_err!("--credentials assume-role requires --role-arn"));
   }
+  Ok(settings)
 }
@@ -0,0 +1,100 @@
+use failure::{Error, format_err};
+use serde_json::Value;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+  Container(String),
+  Task(String),
+  TaskId,
+  ClusterName,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DimensionSpec {
+  pub name: String,
+  pub source: Source,
+}
+
+impl DimensionSpec {
+  pub fn new(name: &str, source: Source) -> DimensionSpec {
+    DimensionSpec { name: String::from(name), source }
+  }
+
+  pub fn extract(&self, task: &Value, container: &Value) -> Option<String> {
+    let value = match &self.source {
+      Source::Container(pointer) => scalar(container.pointer(pointer)?)?,
+      Source::Task(pointer) => scalar(task.pointer(pointer)?)?,
+      Source::TaskId => last_segment(task["TaskARN"].as_str()?),
+      Source::ClusterName => last_segment(task["Cluster"].as_str()?),
+    };
+    Some(value).filter(|v| !v.is_empty())
+  }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+  match value {
+    Value::String(s) => Some(s.clone()),
+    Value::Number(n) => Some(n.to_string()),
+    Value::Bool(b) => Some(b.to_string()),
+    _ => None,
+  }
+}
+
+fn last_segment(arn: &str) -> String {
+  String::from(arn.rsplit('/').next().unwrap_or(arn))
+}
+
+impl FromStr for Source {
+  type Err = Error;
+
+  fn from_str(source: &str) -> Result<Source, Error> {
+    match source {
+      "Name" | "DockerId" | "DockerName" | "Image" | "ImageID" => Ok(Source::Container(format!("/{}", source))),
+      "Cluster" | "Family" | "Revision" | "TaskARN" | "AvailabilityZone" => Ok(Source::Task(format!("/{}", source))),
+      "TaskId" => Ok(Source::TaskId),
+      "ClusterName" => Ok(Source::ClusterName),
+      _ if source.starts_with("container:/") => Ok(Source::Container(String::from(&source["container:".len()..]))),
+      _ if source.starts_with("task:/") => Ok(Source::Task(String::from(&source["task:".len()..]))),
+      _ => Err(format_err!("Unknown dimension source {}", source)),
+    }
+  }
+}
+
+impl FromStr for DimensionSpec {
+  type Err = Error;
+
+  fn from_str(spec: &str) -> Result<DimensionSpec, Error> {
+    match spec.splitn(2, '=').collect::<Vec<&str>>().as_slice() {
+      [name, source] if !name.is_empty() => Ok(DimensionSpec::new(name, source.parse()?)),
+      _ => Err(format_err!("Expected NAME=SOURCE but got {}", spec)),
+    }
+  }
+}
+
+pub fn default_specs() -> Vec<DimensionSpec> {
+  vec![DimensionSpec::new("task", Source::Container(String::from("/Name")))]
+}
+
+pub fn resource_attribute(name: &str, value: String) -> (String, String) {
+  if name == "aws.ecs.cluster.arn" && !value.starts_with("arn:") {
+    (String::from("aws.ecs.cluster.name"), value)
+  } else {
+    (String::from(name), value)
+  }
+}
+
+pub fn resource_specs() -> Vec<DimensionSpec> {
+  vec![
+    DimensionSpec::new("aws.ecs.cluster.arn", Source::Task(String::from("/Cluster"))),
+    DimensionSpec::new("aws.ecs.task.arn", Source::Task(String::from("/TaskARN"))),
+    DimensionSpec::new("aws.ecs.task.family", Source::Task(String::from("/Family"))),
+    DimensionSpec::new("aws.ecs.task.revision", Source::Task(String::from("/Revision"))),
+    DimensionSpec::new("aws.ecs.launchtype", Source::Task(String::from("/LaunchType"))),
+    DimensionSpec::new("cloud.availability_zone", Source::Task(String::from("/AvailabilityZone"))),
+    DimensionSpec::new("aws.ecs.container.arn", Source::Container(String::from("/ContainerARN"))),
+    DimensionSpec::new("container.id", Source::Container(String::from("/DockerId"))),
+    DimensionSpec::new("container.name", Source::Container(String::from("/Name"))),
+    DimensionSpec::new("container.image.name", Source::Container(String::from("/Image"))),
+  ]
+}
@@ -0,0 +1,87 @@
+use crate::sample::Sample;
+use crate::sink::{Action, Sink};
+use log::warn;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+pub const MAX_METRICS_PER_DOCUMENT: usize = 100;
+pub const MAX_DIMENSIONS: usize = 30;
+
+type GroupKey<'a> = (i64, Vec<(&'a str, &'a str)>);
+
+pub struct EmfSink<W: Write> {
+  namespace: String,
+  out: W,
+}
+
+impl<W: Write> EmfSink<W> {
+  pub fn new(namespace: &str, out: W) -> EmfSink<W> {
+    EmfSink { namespace: String::from(namespace), out }
+  }
+
+  pub fn into_inner(self) -> W {
+    self.out
+  }
+}
+
+impl<W: Write> Sink for EmfSink<W> {
+  fn name(&self) -> &str {
+    "emf"
+  }
+
+  fn batch_len(&self, pending: &[Sample]) -> usize {
+    pending.len()
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action> {
+    for document in documents(&self.namespace, batch) {
+      writeln!(self.out, "{}", document).map_err(|err| Action::Retry(format!("{}", err)))?;
+    }
+    self.out.flush().map_err(|err| Action::Retry(format!("{}", err)))
+  }
+}
+
+pub fn documents(namespace: &str, samples: &[Sample]) -> Vec<Value> {
+  let mut groups: BTreeMap<GroupKey, Vec<&Sample>> = BTreeMap::new();
+  for sample in samples {
+    let dimensions = sample.dimensions.iter().map(|d| (d.name.as_str(), d.value.as_str())).collect();
+    groups.entry((sample.timestamp.timestamp_millis(), dimensions)).or_default().push(sample);
+  }
+  let mut documents = Vec::new();
+  for ((timestamp, dimensions), samples) in groups {
+    if dimensions.len() > MAX_DIMENSIONS {
+      warn!("EMF allows {} dimensions, dropping {:?}", MAX_DIMENSIONS, &dimensions[MAX_DIMENSIONS..]);
+    }
+    let dimensions = &dimensions[..dimensions.len().min(MAX_DIMENSIONS)];
+    let mut chunk: Vec<&Sample> = Vec::new();
+    for sample in samples {
+      if chunk.len() == MAX_METRICS_PER_DOCUMENT || chunk.iter().any(|s| s.name == sample.name) {
+        documents.push(document(namespace, timestamp, dimensions, &chunk));
+        chunk.clear();
+      }
+      chunk.push(sample);
+    }
+    documents.push(document(namespace, timestamp, dimensions, &chunk));
+  }
+  documents
+}
+
+fn document(namespace: &str, timestamp: i64, dimensions: &[(&str, &str)], samples: &[&Sample]) -> Value {
+  let mut document = Map::new();
+  document.insert(String::from("_aws"), json!({
+    "Timestamp": timestamp,
+    "CloudWatchMetrics": [{
+      "Namespace": namespace,
+      "Dimensions": [dimensions.iter().map(|(name, _)| name).collect::<Vec<_>>()],
+      "Metrics": samples.iter().map(|s| json!({"Name": s.name, "Unit": s.unit})).collect::<Vec<_>>(),
+    }],
+  }));
+  for (name, value) in dimensions {
+    document.insert(String::from(*name), json!(value));
+  }
+  for sample in samples {
+    document.insert(sample.name.clone(), json!(sample.value));
+  }
+  Value::Object(document)
+}
@@ -0,0 +1,95 @@
+use crate::sample::Sample;
+use crate::sink::{self, Action, Sink};
+use failure::Error;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use reqwest::Client as HttpClient;
+use std::cmp::min;
+use std::io::Write;
+
+const MAX_LINES_PER_REQUEST: usize = 5000;
+
+pub struct InfluxSink {
+  bucket: String,
+  http: HttpClient,
+  org: String,
+  token: Option<String>,
+  url: String,
+}
+
+impl InfluxSink {
+  pub fn new(http: HttpClient, base_url: &str, org: &str, bucket: &str, token: Option<String>) -> InfluxSink {
+    InfluxSink {
+      bucket: String::from(bucket),
+      http,
+      org: String::from(org),
+      token,
+      url: format!("{}/api/v2/write", base_url.trim_end_matches('/')),
+    }
+  }
+}
+
+impl Sink for InfluxSink {
+  fn name(&self) -> &str {
+    "influxdb"
+  }
+
+  fn batch_len(&self, pending: &[Sample]) -> usize {
+    min(pending.len(), MAX_LINES_PER_REQUEST)
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action> {
+    let lines: Vec<String> = batch.iter().filter_map(line_from_sample).collect();
+    if lines.is_empty() {
+      return Ok(());
+    }
+    let body = compress(&lines.join("\n")).map_err(Action::Fail)?;
+    let mut request = self.http.post(&self.url)
+      .query(&[("org", &self.org), ("bucket", &self.bucket), ("precision", &String::from("ns"))])
+      .header("Content-Type", "text/plain; charset=utf-8")
+      .header("Content-Encoding", "gzip")
+      .body(body);
+    if let Some(token) = &self.token {
+      request = request.header("Authorization", format!("Token {}", token));
+    }
+    let mut response = request.send().map_err(|err| Action::Retry(format!("{}", err)))?;
+    sink::classify_http_response("InfluxDB", response.status(), &response.text().unwrap_or_default())
+  }
+}
+
+pub fn compress(body: &str) -> Result<Vec<u8>, Error> {
+  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(body.as_bytes())?;
+  Ok(encoder.finish()?)
+}
+
+pub fn line_from_sample(sample: &Sample) -> Option<String> {
+  if !sample.value.is_finite() {
+    return None;
+  }
+  let timestamp = sample.timestamp.timestamp().checked_mul(1_000_000_000)?
+    .checked_add(i64::from(sample.timestamp.timestamp_subsec_nanos()))?;
+  let mut tags: Vec<(&str, &str)> = sample.dimensions.iter()
+    .filter(|d| !d.value.is_empty())
+    .map(|d| (d.name.as_str(), d.value.as_str()))
+    .chain(Some(("unit", sample.unit.as_str())))
+    .collect();
+  tags.sort();
+  let mut line = escape(&sample.name, &[',', ' ']);
+  for (name, value) in tags {
+    line.push_str(&format!(",{}={}", escape(name, &[',', '=', ' ']), escape(value, &[',', '=', ' '])));
+  }
+  line.push_str(&format!(" value={:?} {}", sample.value, timestamp));
+  Some(line)
+}
+
+fn escape(value: &str, special: &[char]) -> String {
+  let mut escaped = String::with_capacity(value.len());
+  for c in value.chars() {
+    if special.contains(&c) || c == '\\' {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
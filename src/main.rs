@@ -1,35 +1,63 @@
 extern crate args;
 extern crate chrono;
 extern crate failure;
+extern crate flate2;
 extern crate getopts;
 extern crate log;
+extern crate rand;
 extern crate reqwest;
 extern crate rusoto_cloudwatch;
 extern crate rusoto_core;
+extern crate rusoto_sts;
 extern crate serde_json;
+extern crate signal_hook;
 extern crate stderrlog;
 
-use failure::Error;
-use log::{info, warn};
+use failure::{Error, format_err};
+use log::{error, info, warn};
 use reqwest::Client as HttpClient;
-use rusoto_cloudwatch::CloudWatchClient;
-use rusoto_core::Region;
-use std::cmp::max;
+use std::cmp::min;
 use std::env::args;
+use std::io::stdout;
 use std::process::exit;
-use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+pub mod accumulator;
+pub mod aws;
 pub mod cloudwatch;
 pub mod config;
+pub mod dimension_spec;
+pub mod emf;
+pub mod influx;
+pub mod metadata;
 pub mod metadata_v2;
+pub mod metadata_v4;
+pub mod metric_spec;
+pub mod otlp;
+pub mod overflow;
+pub mod prometheus;
+pub mod rates;
+pub mod retry;
+pub mod route;
+pub mod sample;
+pub mod shutdown;
+pub mod sink;
+pub mod spool;
+pub mod statsd;
 #[cfg(test)] pub mod tests;
 
-pub fn maintain_queue<F>(queue: &mut cloudwatch::Metrics, max_size: usize, action: Box<F>) -> Result<(), Error>
-    where F: Fn(&cloudwatch::Metrics) -> Result<usize, Error> {
-  let processed_items = action(&queue)?;
-  let queue_overflow = max(queue.len() as isize - max_size as isize, 0) as usize;
-  queue.drain(..max(processed_items, queue_overflow));
+const SCRAPE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+pub fn maintain_queue<F>(
+  queue: &mut sample::Samples,
+  max_size: usize,
+  overflow: &mut overflow::Overflow,
+  mut action: Box<F>
+) -> Result<(), Error>
+    where F: FnMut(&sample::Samples) -> Result<usize, Error> {
+  let processed_items = min(action(queue)?, queue.len());
+  queue.drain(..processed_items);
+  overflow.cull(queue, max_size, processed_items);
   Ok(())
 }
 
@@ -41,6 +69,69 @@ fn setup_logging(configuration: &config::Configuration) -> Result<(), Error> {
   Ok(())
 }
 
+fn build_sink(kind: sink::SinkKind, configuration: &config::Configuration) -> Result<Box<dyn sink::Sink + Send>, Error> {
+  Ok(match kind {
+    sink::SinkKind::CloudWatch => Box::new(cloudwatch::CloudWatchSink::with_limits(
+      aws::cloudwatch_client(&configuration.aws)?,
+      &configuration.namespace,
+      configuration.cloudwatch_limits
+    ).with_retry(configuration.cloudwatch_retry).with_high_resolution(&configuration.high_resolution)),
+    sink::SinkKind::Prometheus => {
+      let prometheus = prometheus::PrometheusSink::new();
+      prometheus.serve(&configuration.prometheus_listen)?;
+      Box::new(prometheus)
+    },
+    sink::SinkKind::StatsD => Box::new(statsd::StatsdSink::new(
+      &configuration.statsd_address,
+      statsd::Flavor::StatsD,
+      &configuration.statsd_prefix,
+      configuration.statsd_max_packet
+    )?),
+    sink::SinkKind::DogStatsD => Box::new(statsd::StatsdSink::new(
+      &configuration.statsd_address,
+      statsd::Flavor::DogStatsD,
+      &configuration.statsd_prefix,
+      configuration.statsd_max_packet
+    )?),
+    sink::SinkKind::Emf => Box::new(emf::EmfSink::new(&configuration.namespace, stdout())),
+    sink::SinkKind::InfluxDB => Box::new(influx::InfluxSink::new(
+      HttpClient::builder().timeout(Duration::from_secs(10)).build()?,
+      &configuration.influx_url,
+      configuration.influx_org.as_ref().ok_or_else(|| format_err!("The influxdb sink requires --influx-org"))?,
+      configuration.influx_bucket.as_ref().ok_or_else(|| format_err!("The influxdb sink requires --influx-bucket"))?,
+      configuration.influx_token.clone().map(|token| token.0)
+    )),
+    sink::SinkKind::Otlp => Box::new(otlp::OtlpSink::new(
+      HttpClient::builder().timeout(Duration::from_secs(10)).build()?,
+      &configuration.otlp_endpoint
+    )),
+  })
+}
+
+fn build_routes(configuration: &config::Configuration) -> Result<Vec<route::Route>, Error> {
+  let mut routes = Vec::new();
+  let mut spools = Vec::new();
+  for sink_config in &configuration.sinks {
+    let route = route::Route::new(
+      build_sink(sink_config.kind, configuration)?,
+      sink_config.queue_size.unwrap_or(configuration.queue_size),
+      sink_config.batch_size
+    ).with_overflow(configuration.queue_overflow);
+    routes.push(match configuration.spool_dir {
+      Some(ref dir) => {
+        let path = spool::path(dir, sink_config.kind.name());
+        if spools.contains(&path) {
+          return Err(format_err!("Two sinks would share the spool file {}", path.display()));
+        }
+        spools.push(path);
+        route.with_spool(dir, sink_config.kind.name(), configuration.spool_limits.clone())?
+      },
+      None => route,
+    });
+  }
+  Ok(routes)
+}
+
 fn main() -> Result<(), Error> {
   let configuration = match config::parse_args(&args().collect())? {
     config::RunMode::Help(usage) => {
@@ -51,20 +142,87 @@ fn main() -> Result<(), Error> {
   };
   setup_logging(&configuration)?;
   warn!("Starting with configuration {:?}", configuration);
-  let mut metrics_queue = cloudwatch::Metrics::new();
-  let client = CloudWatchClient::new(Region::default());
+  let shutdown = shutdown::Shutdown::register()?;
+  let workers = build_routes(&configuration)?.into_iter()
+    .map(route::Route::spawn)
+    .collect::<Result<Vec<_>, _>>()?;
   let http = HttpClient::builder()
     .timeout(Duration::from_secs(2))
     .build()?;
-  loop {
-    let metadata = metadata_v2::task_metadata(&http, &configuration.base_url)?;
-    let stats = metadata_v2::container_stats(&http, &configuration.base_url)?;
-    cloudwatch::metrics_from_stats(&mut metrics_queue, stats, &metadata);
-    maintain_queue(&mut metrics_queue, configuration.queue_size, Box::new(|metrics: &cloudwatch::Metrics| {
-      let sent_metrics = cloudwatch::report_to_cloudwatch(&client, &configuration.namespace, &metrics)?;
-      info!("Reported {}/{} metrics on {} containers", sent_metrics, metrics.len(), metadata.len());
-      Ok(sent_metrics)
-    }))?;
-    sleep(configuration.interval);
+  let endpoint = metadata::Endpoint::detect(configuration.base_url.as_deref());
+  info!("Using metadata endpoint {:?}", endpoint);
+  let scrape = |retries| retry::with_backoff(retries, SCRAPE_RETRY_DELAY, || {
+    let metadata = endpoint.task_metadata(&http, &configuration.dimensions)?;
+    let stats = endpoint.container_stats(&http, &configuration.metrics, configuration.network_reporting)?;
+    Ok((metadata, stats))
+  });
+  let mut rates = rates::RateTracker::new();
+  let mut scrape_tolerance = retry::FailureTolerance::new(configuration.max_scrape_failures);
+  let mut container_count = 0;
+  let mut accumulator = configuration.sample_interval
+    .map(|_| accumulator::Accumulator::new(configuration.sample_distribution));
+  let sample_interval = configuration.sample_interval.unwrap_or(configuration.interval);
+  let mut tick = Instant::now();
+  let mut next_report = tick + configuration.interval;
+  let mut gave_up = false;
+  let mut samples = loop {
+    let mut samples = sample::Samples::new();
+    match scrape_tolerance.check(scrape(configuration.scrape_retries)) {
+      Ok(Some((metadata, mut stats))) => {
+        rates.update(&mut stats);
+        container_count = metadata.len();
+        sample::metrics_from_stats(&mut samples, stats, &metadata);
+      },
+      Ok(None) => sample::scrape_failure(&mut samples, sample::now()),
+      Err(err) => {
+        error!("Giving up after {} failed scrapes in a row: {}", configuration.max_scrape_failures, err);
+        sample::scrape_failure(&mut samples, sample::now());
+        gave_up = true;
+        break samples;
+      },
+    }
+    let report = match accumulator.as_mut() {
+      Some(accumulator) => {
+        accumulator.add(&samples);
+        if Instant::now() >= next_report {
+          next_report = shutdown::next_tick(next_report, configuration.interval, Instant::now());
+          Some(accumulator.report())
+        } else {
+          None
+        }
+      },
+      None => Some(samples),
+    };
+    if let Some(samples) = report {
+      for worker in &workers {
+        worker.report(&samples, container_count);
+      }
+    }
+    tick = shutdown::next_tick(tick, sample_interval, Instant::now());
+    if shutdown.sleep_until(tick) {
+      warn!("Shutdown requested, taking a final sample");
+      let mut samples = sample::Samples::new();
+      match scrape(0) {
+        Ok((metadata, mut stats)) => {
+          rates.update(&mut stats);
+          sample::metrics_from_stats(&mut samples, stats, &metadata);
+        },
+        Err(err) => warn!("Final sample failed: {}", err),
+      }
+      break samples;
+    }
+  };
+  let deadline = Instant::now() + configuration.shutdown_deadline;
+  if let Some(accumulator) = accumulator.as_mut() {
+    accumulator.add(&samples);
+    samples = accumulator.report();
+  }
+  for worker in &workers {
+    worker.shutdown(&samples, deadline);
+  }
+  let mut flushed = true;
+  for worker in workers {
+    flushed &= worker.join();
   }
+  exit(if flushed && !gave_up { 0 } else { 1 });
 }
@@ -0,0 +1,47 @@
+use crate::dimension_spec::DimensionSpec;
+use crate::metadata_v2::{self, Metadata, NetworkReporting, Stats};
+use crate::metadata_v4;
+use crate::metric_spec::MetricSpec;
+use failure::Error;
+use log::warn;
+use reqwest::Client as HttpClient;
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq)]
+pub enum Endpoint {
+  V2(String),
+  V4(String, String),
+}
+
+impl Endpoint {
+  pub fn detect(v2_base_url: Option<&str>) -> Endpoint {
+    match (metadata_v4::endpoint_from_env(), v2_base_url) {
+      (Some(uri), None) => Endpoint::V4(uri, metadata_v2::DEFAULT_BASE_URL.to_owned()),
+      (Some(uri), Some(base_url)) => {
+        warn!("Using --metadata-endpoint {} instead of the v4 endpoint {}", base_url, uri);
+        Endpoint::V2(base_url.to_owned())
+      },
+      (None, base_url) => Endpoint::V2(base_url.unwrap_or(metadata_v2::DEFAULT_BASE_URL).to_owned()),
+    }
+  }
+
+  pub fn task_metadata(&self, http: &HttpClient, dimensions: &[DimensionSpec]) -> Result<HashMap<String, Metadata>, Error> {
+    match self {
+      Endpoint::V2(base_url) => metadata_v2::task_metadata(http, base_url, dimensions),
+      Endpoint::V4(endpoint, fallback) => metadata_v4::task_metadata(http, endpoint, dimensions).or_else(|err| {
+        warn!("Task metadata from the v4 endpoint failed, falling back to v2: {}", err);
+        metadata_v2::task_metadata(http, fallback, dimensions)
+      }),
+    }
+  }
+
+  pub fn container_stats(&self, http: &HttpClient, metrics: &[MetricSpec], network: NetworkReporting) -> Result<Vec<Stats>, Error> {
+    match self {
+      Endpoint::V2(base_url) => metadata_v2::container_stats(http, base_url, metrics, network),
+      Endpoint::V4(endpoint, fallback) => metadata_v4::container_stats(http, endpoint, metrics, network).or_else(|err| {
+        warn!("Container stats from the v4 endpoint failed, falling back to v2: {}", err);
+        metadata_v2::container_stats(http, fallback, metrics, network)
+      }),
+    }
+  }
+}
@@ -1,9 +1,12 @@
 use chrono::{DateTime, FixedOffset};
-use failure::Error;
-use log::debug;
+use crate::dimension_spec::{self, DimensionSpec};
+use crate::metric_spec::MetricSpec;
+use crate::sample::Dimension;
+use failure::{Error, Fail};
+use log::{debug, warn};
 use reqwest::Client as HttpClient;
-use rusoto_cloudwatch::Dimension;
 use serde_json::Value;
+use std::fmt;
 use std::iter::FromIterator;
 use std::collections::HashMap;
 
@@ -11,7 +14,9 @@ use std::collections::HashMap;
 pub struct Metadata {
   #[allow(dead_code)]
   pub container_id: String,
+  pub cpu_limit: Option<f64>,
   pub dimensions: Vec<Dimension>,
+  pub resource: Vec<Dimension>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -24,59 +29,258 @@ pub struct Metric {
 #[derive(Debug, PartialEq)]
 pub struct Stats {
   pub container_id: String,
+  pub counters: Vec<Metric>,
+  pub cumulative: Vec<Metric>,
   pub metrics: Vec<Metric>,
   pub timestamp: DateTime<FixedOffset>,
 }
 
-const DIMENSIONS_TO_COLLECT: [(&str, &str); 1] = [
-  ("/Name", "task"),
-];
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NetworkReporting {
+  #[default]
+  Summed,
+  PerInterface,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+  UnexpectedType { field: String, expected: &'static str, document: String },
+  InvalidTimestamp { timestamp: String, document: String },
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ParseError::UnexpectedType { field, expected, document } =>
+        write!(f, "Expected {} to be {} in {}", field, expected, document),
+      ParseError::InvalidTimestamp { timestamp, document } =>
+        write!(f, "Invalid timestamp {} in {}", timestamp, document),
+    }
+  }
+}
+
+impl Fail for ParseError {}
+
+fn unexpected_type(field: &str, expected: &'static str, document: &Value) -> ParseError {
+  ParseError::UnexpectedType { field: String::from(field), expected, document: document.to_string() }
+}
 
-pub fn task_metadata(http: &HttpClient, base_url: &str) -> Result<HashMap<String, Metadata>, Error> {
+fn string_field<'a>(document: &'a Value, field: &str) -> Result<&'a str, ParseError> {
+  document[field].as_str().ok_or_else(|| unexpected_type(field, "a string", document))
+}
+
+pub const DEFAULT_BASE_URL: &str = "http://169.254.170.2";
+
+pub fn task_metadata(http: &HttpClient, base_url: &str, dimensions: &[DimensionSpec]) -> Result<HashMap<String, Metadata>, Error> {
   let body: Value = http.get(&format!("{}/v2/metadata", base_url)).send()?.json()?;
   debug!("Received metadata {}", body);
-  let containers: Vec<Value> = match body.get("Containers") {
-      Some(c) => c.as_array().unwrap().clone(),
-      None => Vec::new(),
-    };
+  Ok(parse_task_metadata(&body, dimensions)?)
+}
+
+pub fn parse_task_metadata(body: &Value, dimensions: &[DimensionSpec]) -> Result<HashMap<String, Metadata>, ParseError> {
+  let containers = match body.get("Containers") {
+    Some(c) => c.as_array().ok_or_else(|| unexpected_type("Containers", "an array", body))?.as_slice(),
+    None => &[],
+  };
+  let cpu_limit = body.pointer("/Limits/CPU").and_then(Value::as_f64);
   let metadata_pairs = containers.iter()
-    .filter(|container| !container["Name"].as_str().unwrap().starts_with("~internal"))
-    .map(|container| (
-      container["DockerId"].as_str().unwrap().to_owned(),
-      Metadata {
-        container_id: container["DockerId"].as_str().unwrap().to_owned(),
-        dimensions: DIMENSIONS_TO_COLLECT.iter().map(|(p, n)|
-          Dimension {
-            name: String::from(*n),
-            value: String::from(container.pointer(p).unwrap().as_str().unwrap()),
-          }
-        ).collect(),
-      })
-    );
+    .filter_map(|container| match container_metadata(body, container, cpu_limit, dimensions) {
+      Ok(metadata) => metadata,
+      Err(err) => {
+        warn!("Skipping container: {}", err);
+        None
+      },
+    });
   Ok(HashMap::from_iter(metadata_pairs))
 }
 
-const METRICS_TO_COLLECT: [(&str, &str, &str); 2] = [
-  ("/memory_stats/max_usage", "max_usage", "Bytes"),
-  ("/memory_stats/usage", "usage", "Bytes"),
-];
+fn container_metadata(body: &Value, container: &Value, cpu_limit: Option<f64>, dimensions: &[DimensionSpec])
+    -> Result<Option<(String, Metadata)>, ParseError> {
+  if string_field(container, "Name")?.starts_with("~internal") {
+    return Ok(None);
+  }
+  let container_id = string_field(container, "DockerId")?.to_owned();
+  Ok(Some((
+    container_id.clone(),
+    Metadata {
+      container_id,
+      cpu_limit,
+      dimensions: dimensions.iter().filter_map(|spec| match spec.extract(body, container) {
+        Some(value) => Some(Dimension {
+          name: spec.name.clone(),
+          value,
+        }),
+        None => {
+          warn!("Dimension {} is missing for container {}", spec.name, container["Name"]);
+          None
+        },
+      }).collect(),
+      resource: dimension_spec::resource_specs().iter()
+        .filter_map(|spec| spec.extract(body, container).map(|value| dimension_spec::resource_attribute(&spec.name, value)))
+        .map(|(name, value)| Dimension { name, value })
+        .collect(),
+    }
+  )))
+}
 
-pub fn container_stats(http: &HttpClient, base_url: &str) -> Result<Vec<Stats>, Error> {
+pub fn container_stats(http: &HttpClient, base_url: &str, metrics: &[MetricSpec], network: NetworkReporting) -> Result<Vec<Stats>, Error> {
   let body: Value = http.get(&format!("{}/v2/stats", base_url)).send()?.json()?;
   debug!("Received stats {}", body);
-  let stats = body.as_object().unwrap().iter()
+  Ok(parse_container_stats(&body, metrics, network)?)
+}
+
+pub fn parse_container_stats(body: &Value, metrics: &[MetricSpec], network: NetworkReporting) -> Result<Vec<Stats>, ParseError> {
+  let stats = body.as_object().ok_or_else(|| unexpected_type("stats", "an object", body))?.iter()
     .filter(|(_, stats)| !stats.is_null())
-    .map(|(id, stats)|
-      Stats {
-        container_id: id.clone(),
-        metrics: METRICS_TO_COLLECT.iter().map(|(p, n, u)| Metric {
-          name: String::from(*n),
-          unit: String::from(*u),
-          value: stats.pointer(p).unwrap().as_f64().unwrap(),
-        }).collect(),
-        timestamp: DateTime::parse_from_rfc3339(stats["read"].as_str().unwrap()).unwrap(),
-      }
-    )
+    .filter_map(|(id, stats)| match single_container_stats(id, stats, metrics, network) {
+      Ok(stats) => Some(stats),
+      Err(err) => {
+        warn!("Skipping stats for container {}: {}", id, err);
+        None
+      },
+    })
     .collect();
   Ok(stats)
 }
+
+fn single_container_stats(id: &str, stats: &Value, metrics: &[MetricSpec], network: NetworkReporting) -> Result<Stats, ParseError> {
+  if !stats.is_object() {
+    return Err(unexpected_type(id, "an object", stats));
+  }
+  let read = string_field(stats, "read")?;
+  let timestamp = DateTime::parse_from_rfc3339(read).map_err(|_| ParseError::InvalidTimestamp {
+    timestamp: String::from(read),
+    document: stats.to_string(),
+  })?;
+  Ok(Stats {
+    container_id: String::from(id),
+    counters: network_counters(stats, network).into_iter().chain(blkio_counters(stats)).collect(),
+    cumulative: throttling_counters(stats),
+    metrics: metrics.iter().filter_map(|spec| match spec.extract(stats) {
+      Some(value) => Some(Metric {
+        name: spec.name.clone(),
+        unit: spec.unit.clone(),
+        value,
+      }),
+      None => {
+        warn!("Metric {} at {} is missing or not numeric for container {}", spec.name, spec.pointer, id);
+        None
+      },
+    }).chain(cpu_metrics(stats)).collect(),
+    timestamp,
+  })
+}
+
+fn cpu_metrics(stats: &Value) -> Vec<Metric> {
+  let mut metrics = Vec::new();
+  if let Some(utilization) = cpu_core_utilization(stats) {
+    metrics.push(Metric {
+      name: String::from("cpu_core_utilization"),
+      unit: String::from("Percent"),
+      value: utilization,
+    });
+  }
+  metrics
+}
+
+fn throttling_counters(stats: &Value) -> Vec<Metric> {
+  let mut counters = Vec::new();
+  if let Some(periods) = stats.pointer("/cpu_stats/throttling_data/throttled_periods").and_then(Value::as_f64) {
+    counters.push(Metric {
+      name: String::from("throttled_periods"),
+      unit: String::from("Count"),
+      value: periods,
+    });
+  }
+  if let Some(nanos) = stats.pointer("/cpu_stats/throttling_data/throttled_time").and_then(Value::as_f64) {
+    counters.push(Metric {
+      name: String::from("throttled_time"),
+      unit: String::from("Microseconds"),
+      value: nanos / 1000.0,
+    });
+  }
+  counters
+}
+
+fn cpu_core_utilization(stats: &Value) -> Option<f64> {
+  let value = |pointer: &str| stats.pointer(pointer).and_then(Value::as_f64);
+  let cpu_delta = value("/cpu_stats/cpu_usage/total_usage")? - value("/precpu_stats/cpu_usage/total_usage")?;
+  let system_delta = value("/cpu_stats/system_cpu_usage")? - value("/precpu_stats/system_cpu_usage")?;
+  if cpu_delta < 0.0 || system_delta <= 0.0 {
+    return None;
+  }
+  let online_cpus = value("/cpu_stats/online_cpus")
+    .or_else(|| stats.pointer("/cpu_stats/cpu_usage/percpu_usage").and_then(Value::as_array).map(|a| a.len() as f64))
+    .filter(|cpus| *cpus > 0.0)
+    .unwrap_or(1.0);
+  Some(cpu_delta / system_delta * online_cpus * 100.0)
+}
+
+pub fn task_cpu_utilization(stats: &Stats, metadata: &Metadata) -> Option<Metric> {
+  let core_utilization = stats.metrics.iter().find(|m| m.name == "cpu_core_utilization")?;
+  let vcpus = metadata.cpu_limit.filter(|limit| *limit > 0.0)?;
+  Some(Metric {
+    name: String::from("cpu_utilization"),
+    unit: String::from("Percent"),
+    value: core_utilization.value / vcpus,
+  })
+}
+
+const NETWORK_COUNTERS: [(&str, &str); 8] = [
+  ("rx_bytes", "Bytes"),
+  ("rx_packets", "Count"),
+  ("rx_errors", "Count"),
+  ("rx_dropped", "Count"),
+  ("tx_bytes", "Bytes"),
+  ("tx_packets", "Count"),
+  ("tx_errors", "Count"),
+  ("tx_dropped", "Count"),
+];
+
+fn network_counters(stats: &Value, network: NetworkReporting) -> Vec<Metric> {
+  let interfaces = match stats.get("networks").and_then(Value::as_object) {
+    Some(interfaces) => interfaces,
+    None => return Vec::new(),
+  };
+  match network {
+    NetworkReporting::Summed => NETWORK_COUNTERS.iter()
+      .map(|(field, unit)| Metric {
+        name: format!("network_{}", field),
+        unit: String::from(*unit),
+        value: interfaces.values().filter_map(|i| i[*field].as_f64()).sum(),
+      })
+      .collect(),
+    NetworkReporting::PerInterface => interfaces.iter()
+      .flat_map(|(interface, counters)| NETWORK_COUNTERS.iter().filter_map(move |(field, unit)|
+        counters[*field].as_f64().map(|value| Metric {
+          name: format!("network_{}_{}", interface, field),
+          unit: String::from(*unit),
+          value,
+        })
+      ))
+      .collect(),
+  }
+}
+
+const BLKIO_COUNTERS: [(&str, &str, &str, &str); 4] = [
+  ("/blkio_stats/io_service_bytes_recursive", "Read", "blkio_read_bytes", "Bytes"),
+  ("/blkio_stats/io_service_bytes_recursive", "Write", "blkio_write_bytes", "Bytes"),
+  ("/blkio_stats/io_serviced_recursive", "Read", "blkio_read_ops", "Count"),
+  ("/blkio_stats/io_serviced_recursive", "Write", "blkio_write_ops", "Count"),
+];
+
+fn blkio_counters(stats: &Value) -> Vec<Metric> {
+  BLKIO_COUNTERS.iter()
+    .filter_map(|(p, op, n, u)| {
+      let entries = stats.pointer(p).and_then(Value::as_array).filter(|entries| !entries.is_empty())?;
+      Some(Metric {
+        name: String::from(*n),
+        unit: String::from(*u),
+        value: entries.iter()
+          .filter(|entry| entry["op"].as_str().is_some_and(|o| o.eq_ignore_ascii_case(op)))
+          .filter_map(|entry| entry["value"].as_f64())
+          .sum(),
+      })
+    })
+    .collect()
+}
@@ -0,0 +1,29 @@
+use crate::dimension_spec::DimensionSpec;
+use crate::metadata_v2::{self, Metadata, NetworkReporting, Stats};
+use crate::metric_spec::MetricSpec;
+use failure::Error;
+use log::debug;
+use reqwest::Client as HttpClient;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+
+const METADATA_URI_VARIABLE: &str = "ECS_CONTAINER_METADATA_URI_V4";
+
+pub fn endpoint_from_env() -> Option<String> {
+  env::var(METADATA_URI_VARIABLE).ok()
+    .map(|uri| uri.trim_end_matches('/').to_owned())
+    .filter(|uri| !uri.is_empty())
+}
+
+pub fn task_metadata(http: &HttpClient, endpoint: &str, dimensions: &[DimensionSpec]) -> Result<HashMap<String, Metadata>, Error> {
+  let body: Value = http.get(&format!("{}/task", endpoint)).send()?.json()?;
+  debug!("Received v4 metadata {}", body);
+  Ok(metadata_v2::parse_task_metadata(&body, dimensions)?)
+}
+
+pub fn container_stats(http: &HttpClient, endpoint: &str, metrics: &[MetricSpec], network: NetworkReporting) -> Result<Vec<Stats>, Error> {
+  let body: Value = http.get(&format!("{}/task/stats", endpoint)).send()?.json()?;
+  debug!("Received v4 stats {}", body);
+  Ok(metadata_v2::parse_container_stats(&body, metrics, network)?)
+}
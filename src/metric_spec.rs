@@ -0,0 +1,90 @@
+use failure::{Error, format_err};
+use serde_json::Value;
+use std::fs::File;
+use std::str::FromStr;
+
+const CLOUDWATCH_UNITS: [&str; 27] = [
+  "Seconds", "Microseconds", "Milliseconds",
+  "Bytes", "Kilobytes", "Megabytes", "Gigabytes", "Terabytes",
+  "Bits", "Kilobits", "Megabits", "Gigabits", "Terabits",
+  "Percent", "Count",
+  "Bytes/Second", "Kilobytes/Second", "Megabytes/Second", "Gigabytes/Second", "Terabytes/Second",
+  "Bits/Second", "Kilobits/Second", "Megabits/Second", "Gigabits/Second", "Terabits/Second",
+  "Count/Second", "None",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricSpec {
+  pub pointer: String,
+  pub name: String,
+  pub unit: String,
+  pub scale: f64,
+}
+
+impl MetricSpec {
+  pub fn new(pointer: &str, name: &str, unit: &str, scale: f64) -> Result<MetricSpec, Error> {
+    if !pointer.starts_with('/') {
+      return Err(format_err!("JSON pointer {} for metric {} must start with /", pointer, name));
+    }
+    if name.is_empty() {
+      return Err(format_err!("Metric at {} has no name", pointer));
+    }
+    if !CLOUDWATCH_UNITS.contains(&unit) {
+      return Err(format_err!("Unknown unit {} for metric {}", unit, name));
+    }
+    if !scale.is_finite() {
+      return Err(format_err!("Scale {} for metric {} is not a finite number", scale, name));
+    }
+    Ok(MetricSpec {
+      pointer: String::from(pointer),
+      name: String::from(name),
+      unit: String::from(unit),
+      scale,
+    })
+  }
+
+  pub fn extract(&self, stats: &Value) -> Option<f64> {
+    stats.pointer(&self.pointer).and_then(Value::as_f64).map(|value| value * self.scale)
+  }
+}
+
+impl FromStr for MetricSpec {
+  type Err = Error;
+
+  fn from_str(spec: &str) -> Result<MetricSpec, Error> {
+    let parts: Vec<&str> = spec.split(',').map(str::trim).collect();
+    match parts.as_slice() {
+      [pointer, name, unit] => MetricSpec::new(pointer, name, unit, 1.0),
+      [pointer, name, unit, scale] => MetricSpec::new(pointer, name, unit, scale.parse()?),
+      _ => Err(format_err!("Expected POINTER,NAME,UNIT[,SCALE] but got {}", spec)),
+    }
+  }
+}
+
+pub fn default_specs() -> Vec<MetricSpec> {
+  vec![
+    MetricSpec::new("/memory_stats/max_usage", "max_usage", "Bytes", 1.0).unwrap(),
+    MetricSpec::new("/memory_stats/usage", "usage", "Bytes", 1.0).unwrap(),
+  ]
+}
+
+pub fn load_specs(path: &str) -> Result<Vec<MetricSpec>, Error> {
+  let body: Value = serde_json::from_reader(File::open(path)?)?;
+  parse_specs(&body)
+}
+
+pub fn parse_specs(body: &Value) -> Result<Vec<MetricSpec>, Error> {
+  let field = |spec: &Value, name: &str| spec[name].as_str()
+    .map(String::from)
+    .ok_or_else(|| format_err!("Metric spec {} lacks string field {}", spec, name));
+  body.as_array().ok_or_else(|| format_err!("Metric spec must be a JSON array"))?
+    .iter()
+    .map(|spec| {
+      let scale = match spec.get("scale") {
+        Some(scale) => scale.as_f64().ok_or_else(|| format_err!("Metric spec {} has non-numeric scale", spec))?,
+        None => 1.0,
+      };
+      MetricSpec::new(&field(spec, "pointer")?, &field(spec, "name")?, &field(spec, "unit")?, scale)
+    })
+    .collect()
+}
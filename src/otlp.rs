@@ -0,0 +1,178 @@
+use chrono::{DateTime, FixedOffset};
+use crate::sample::{self, Dimension, Kind, Sample};
+use crate::sink::{self, Action, Sink};
+use reqwest::Client as HttpClient;
+use serde_json::{json, Value};
+use std::cmp::min;
+use std::collections::HashMap;
+
+const MAX_DATA_POINTS_PER_REQUEST: usize = 1000;
+const AGGREGATION_TEMPORALITY_DELTA: u8 = 1;
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+pub struct OtlpSink {
+  http: HttpClient,
+  reported: HashMap<String, i64>,
+  started: i64,
+  url: String,
+}
+
+impl OtlpSink {
+  pub fn new(http: HttpClient, endpoint: &str) -> OtlpSink {
+    OtlpSink {
+      http,
+      reported: HashMap::new(),
+      started: unix_nanos(&sample::now()).unwrap_or_default(),
+      url: format!("{}/v1/metrics", endpoint.trim_end_matches('/')),
+    }
+  }
+
+  pub fn request(&self, batch: &[Sample]) -> Value {
+    export_request(batch, &self.start_times(batch).0)
+  }
+
+  fn start_times(&self, batch: &[Sample]) -> (Vec<Option<i64>>, HashMap<String, i64>) {
+    let mut reported = self.reported.clone();
+    let start_times = batch.iter().map(|sample| match (sample.kind, unix_nanos(&sample.timestamp)) {
+      (Kind::Sum, Some(time)) => {
+        let key = format!("{}{:?}{:?}", sample.name, sample.dimensions, sample.resource);
+        Some(reported.insert(key, time).unwrap_or(self.started))
+      },
+      (Kind::Cumulative, Some(_)) => Some(self.started),
+      _ => None,
+    }).collect();
+    (start_times, reported)
+  }
+}
+
+impl Sink for OtlpSink {
+  fn name(&self) -> &str {
+    "otlp"
+  }
+
+  fn batch_len(&self, pending: &[Sample]) -> usize {
+    min(pending.len(), MAX_DATA_POINTS_PER_REQUEST)
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action> {
+    let (start_times, reported) = self.start_times(batch);
+    let mut response = self.http.post(&self.url)
+      .json(&export_request(batch, &start_times))
+      .send()
+      .map_err(|err| Action::Retry(format!("{}", err)))?;
+    sink::classify_http_response("OTLP collector", response.status(), &response.text().unwrap_or_default())?;
+    self.reported = reported;
+    Ok(())
+  }
+}
+
+type Point<'a> = (&'a Sample, i64, Option<i64>);
+
+pub fn export_request(samples: &[Sample], start_times: &[Option<i64>]) -> Value {
+  let mut resources: Vec<(&[Dimension], Vec<Point>)> = Vec::new();
+  for (i, sample) in samples.iter().enumerate() {
+    let time = match unix_nanos(&sample.timestamp) {
+      Some(time) => time,
+      None => continue,
+    };
+    let point = (sample, time, start_times.get(i).cloned().unwrap_or_default());
+    match resources.iter_mut().find(|(resource, _)| *resource == &sample.resource[..]) {
+      Some((_, members)) => members.push(point),
+      None => resources.push((&sample.resource, vec![point])),
+    }
+  }
+  json!({
+    "resourceMetrics": resources.iter().map(|(resource, samples)| json!({
+      "resource": {
+        "attributes": resource_attributes(resource),
+      },
+      "scopeMetrics": [{
+        "scope": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")},
+        "metrics": metrics(samples),
+      }],
+    })).collect::<Vec<_>>(),
+  })
+}
+
+fn resource_attributes(resource: &[Dimension]) -> Vec<Value> {
+  let mut attributes = vec![attribute("cloud.provider", "aws"), attribute("cloud.platform", "aws_ecs")];
+  attributes.extend(resource.iter().map(|d| attribute(&d.name, &d.value)));
+  attributes
+}
+
+fn metrics(points: &[Point]) -> Vec<Value> {
+  let mut metrics: Vec<(&Sample, Vec<Value>)> = Vec::new();
+  for (sample, time, start_time) in points {
+    let point = data_point(sample, *time, *start_time);
+    match metrics.iter_mut().find(|(m, _)| m.name == sample.name && m.unit == sample.unit && m.kind == sample.kind) {
+      Some((_, points)) => points.push(point),
+      None => metrics.push((sample, vec![point])),
+    }
+  }
+  metrics.into_iter().map(|(sample, points)| {
+    let mut metric = json!({"name": sample.name, "unit": unit(&sample.unit)});
+    match sample.kind {
+      Kind::Gauge => metric["gauge"] = json!({"dataPoints": points}),
+      Kind::Sum => metric["sum"] = json!({
+        "aggregationTemporality": AGGREGATION_TEMPORALITY_DELTA,
+        "isMonotonic": true,
+        "dataPoints": points,
+      }),
+      Kind::Cumulative => metric["sum"] = json!({
+        "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+        "isMonotonic": true,
+        "dataPoints": points,
+      }),
+    }
+    metric
+  }).collect()
+}
+
+fn data_point(sample: &Sample, time: i64, start_time: Option<i64>) -> Value {
+  let mut point = json!({
+    "attributes": sample.dimensions.iter().map(|d| attribute(&d.name, &d.value)).collect::<Vec<_>>(),
+    "timeUnixNano": time.to_string(),
+    "asDouble": sample.value,
+  });
+  if let Some(start_time) = start_time {
+    point["startTimeUnixNano"] = json!(start_time.to_string());
+  }
+  point
+}
+
+fn unix_nanos(timestamp: &DateTime<FixedOffset>) -> Option<i64> {
+  timestamp.timestamp().checked_mul(1_000_000_000)?.checked_add(i64::from(timestamp.timestamp_subsec_nanos()))
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+  json!({"key": key, "value": {"stringValue": value}})
+}
+
+pub fn unit(cloudwatch_unit: &str) -> String {
+  let (numerator, denominator) = match cloudwatch_unit.find('/') {
+    Some(i) => (&cloudwatch_unit[..i], Some(&cloudwatch_unit[i + 1..])),
+    None => (cloudwatch_unit, None),
+  };
+  let numerator = match numerator {
+    "Seconds" | "Second" => "s",
+    "Microseconds" => "us",
+    "Milliseconds" => "ms",
+    "Bytes" => "By",
+    "Kilobytes" => "kBy",
+    "Megabytes" => "MBy",
+    "Gigabytes" => "GBy",
+    "Terabytes" => "TBy",
+    "Bits" => "bit",
+    "Kilobits" => "kbit",
+    "Megabits" => "Mbit",
+    "Gigabits" => "Gbit",
+    "Terabits" => "Tbit",
+    "Percent" => "%",
+    "Count" | "None" => "1",
+    other => other,
+  };
+  match denominator {
+    Some(_) => format!("{}/s", numerator),
+    None => String::from(numerator),
+  }
+}
@@ -0,0 +1,169 @@
+use chrono::Timelike;
+use crate::sample::{Kind, Sample, Samples, Statistics};
+use failure::{Error, format_err};
+use rand::Rng;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OverflowPolicy {
+  #[default]
+  DropOldest,
+  DropNewest,
+  Reservoir,
+  KeepExtremes,
+  Compact,
+}
+
+impl FromStr for OverflowPolicy {
+  type Err = Error;
+
+  fn from_str(policy: &str) -> Result<OverflowPolicy, Error> {
+    match policy {
+      "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+      "drop-newest" => Ok(OverflowPolicy::DropNewest),
+      "reservoir" => Ok(OverflowPolicy::Reservoir),
+      "keep-extremes" => Ok(OverflowPolicy::KeepExtremes),
+      "compact" => Ok(OverflowPolicy::Compact),
+      _ => Err(format_err!("Unknown overflow policy {}", policy)),
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct Overflow {
+  policy: OverflowPolicy,
+  retained: usize,
+  seen: usize,
+}
+
+impl Overflow {
+  pub fn new(policy: OverflowPolicy) -> Overflow {
+    Overflow { policy, retained: 0, seen: 0 }
+  }
+
+  pub fn cull(&mut self, queue: &mut Samples, max_size: usize, processed: usize) {
+    let retained = self.retained.saturating_sub(processed);
+    if queue.len() > max_size {
+      match self.policy {
+        OverflowPolicy::DropOldest => { queue.drain(..queue.len() - max_size); },
+        OverflowPolicy::DropNewest => queue.truncate(max_size),
+        OverflowPolicy::Reservoir => self.sample_reservoir(queue, max_size, retained),
+        OverflowPolicy::KeepExtremes => keep_extremes(queue, max_size),
+        OverflowPolicy::Compact => compact(queue, max_size),
+      }
+    } else {
+      self.seen = queue.len();
+    }
+    self.retained = queue.len();
+  }
+
+  fn sample_reservoir(&mut self, queue: &mut Samples, max_size: usize, retained: usize) {
+    let retained = retained.min(max_size).min(queue.len());
+    let arrivals = queue.split_off(retained);
+    let mut rng = rand::thread_rng();
+    self.seen = self.seen.max(retained);
+    for sample in arrivals {
+      if queue.len() < max_size {
+        queue.push(sample);
+      } else {
+        let slot = rng.gen_range(0, self.seen + 1);
+        if slot < max_size {
+          queue.remove(slot);
+          queue.push(sample);
+        }
+      }
+      self.seen += 1;
+    }
+  }
+}
+
+fn same_series(a: &Sample, b: &Sample) -> bool {
+  a.name == b.name && a.dimensions == b.dimensions && a.resource == b.resource
+}
+
+fn keep_extremes(queue: &mut Samples, max_size: usize) {
+  let mut series: Vec<(usize, usize, usize)> = Vec::new();
+  for (i, sample) in queue.iter().enumerate() {
+    match series.iter_mut().find(|(first, _, _)| same_series(&queue[*first], sample)) {
+      Some((_, min, max)) => {
+        if sample.value < queue[*min].value {
+          *min = i;
+        }
+        if sample.value > queue[*max].value {
+          *max = i;
+        }
+      },
+      None => series.push((i, i, i)),
+    }
+  }
+  let mut extreme = vec![false; queue.len()];
+  for (_, min, max) in series {
+    extreme[min] = true;
+    extreme[max] = true;
+  }
+  let mut excess = queue.len() - max_size;
+  let mut index = 0;
+  queue.retain(|_| {
+    let keep = excess == 0 || extreme[index];
+    if !keep {
+      excess -= 1;
+    }
+    index += 1;
+    keep
+  });
+  if queue.len() > max_size {
+    let overflow = queue.len() - max_size;
+    queue.drain(..overflow);
+  }
+}
+
+fn minute(sample: &Sample) -> i64 {
+  sample.timestamp.timestamp().div_euclid(60)
+}
+
+fn same_bucket(a: &Sample, b: &Sample) -> bool {
+  same_series(a, b) && a.unit == b.unit && a.kind == b.kind && minute(a) == minute(b)
+}
+
+fn compacted_value(kind: Kind, statistics: &Statistics) -> f64 {
+  match kind {
+    Kind::Gauge => statistics.average(),
+    Kind::Sum => statistics.sum,
+    Kind::Cumulative => statistics.maximum,
+  }
+}
+
+fn compact(queue: &mut Samples, max_size: usize) {
+  let mut buckets: Vec<usize> = Vec::new();
+  let mut prefix = 0;
+  while prefix < queue.len() && buckets.len() + queue.len() - prefix > max_size {
+    if !buckets.iter().any(|first| same_bucket(&queue[*first], &queue[prefix])) {
+      buckets.push(prefix);
+    }
+    prefix += 1;
+  }
+  let mut compacted: Samples = Vec::with_capacity(buckets.len());
+  for sample in queue.drain(..prefix) {
+    let statistics = Statistics::of(&sample);
+    match compacted.iter_mut().find(|c| same_bucket(c, &sample)) {
+      Some(bucket) => {
+        let merged = Statistics::of(bucket).merge(&statistics);
+        bucket.statistics = Some(merged);
+        bucket.value = compacted_value(bucket.kind, &merged);
+      },
+      None => compacted.push(Sample {
+        distribution: None,
+        statistics: Some(statistics),
+        timestamp: sample.timestamp.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(sample.timestamp),
+        value: compacted_value(sample.kind, &statistics),
+        ..sample
+      }),
+    }
+  }
+  compacted.append(queue);
+  *queue = compacted;
+  if queue.len() > max_size {
+    let overflow = queue.len() - max_size;
+    queue.drain(..overflow);
+  }
+}
@@ -0,0 +1,178 @@
+use chrono::{DateTime, FixedOffset};
+use crate::sample::{self, Kind, Sample};
+use crate::sink::{Action, Sink};
+use failure::Error;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+const STALE_AFTER_SECONDS: i64 = 300;
+
+type SeriesKey = (String, Vec<(String, String)>);
+type Series = BTreeMap<SeriesKey, Sample>;
+
+#[derive(Clone, Default)]
+pub struct PrometheusSink {
+  series: Arc<Mutex<Series>>,
+}
+
+impl PrometheusSink {
+  pub fn new() -> PrometheusSink {
+    PrometheusSink::default()
+  }
+
+  pub fn serve(&self, listen: &str) -> Result<SocketAddr, Error> {
+    let listener = TcpListener::bind(listen)?;
+    let address = listener.local_addr()?;
+    info!("Serving Prometheus metrics on http://{}/metrics", address);
+    let series = self.series.clone();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        if let Err(err) = stream.map_err(Error::from).and_then(|stream| respond(stream, &series)) {
+          warn!("Failed to serve Prometheus scrape: {}", err);
+        }
+      }
+    });
+    Ok(address)
+  }
+
+  pub fn render(&self) -> String {
+    self.render_at(sample::now())
+  }
+
+  pub fn render_at(&self, now: DateTime<FixedOffset>) -> String {
+    render(&self.series.lock().unwrap(), now)
+  }
+}
+
+impl Sink for PrometheusSink {
+  fn name(&self) -> &str {
+    "prometheus"
+  }
+
+  fn batch_len(&self, pending: &[Sample]) -> usize {
+    pending.len()
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action> {
+    let mut series = self.series.lock().unwrap();
+    for sample in batch {
+      let key = (sample.name.clone(), sample.dimensions.iter().map(|d| (d.name.clone(), d.value.clone())).collect());
+      match series.entry(key) {
+        Entry::Occupied(mut entry) => {
+          let existing = entry.get_mut();
+          if sample.kind == Kind::Sum && existing.kind == Kind::Sum {
+            existing.value += sample.value;
+            existing.timestamp = existing.timestamp.max(sample.timestamp);
+          } else if existing.timestamp <= sample.timestamp {
+            *existing = sample.clone();
+          }
+        },
+        Entry::Vacant(entry) => {
+          entry.insert(sample.clone());
+        },
+      }
+    }
+    if let Some(newest) = series.values().map(|s| s.timestamp).max() {
+      series.retain(|_, s| s.kind == Kind::Sum || (newest - s.timestamp).num_seconds() < STALE_AFTER_SECONDS);
+    }
+    Ok(())
+  }
+}
+
+fn respond(mut stream: TcpStream, series: &Mutex<Series>) -> Result<(), Error> {
+  stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+  let mut request_line = String::new();
+  {
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+      header.clear();
+    }
+  }
+  let (status, body) = match request_line.split_whitespace().nth(1) {
+    Some("/metrics") => ("200 OK", render(&series.lock().unwrap(), sample::now())),
+    _ => ("404 Not Found", String::from("Not found\n")),
+  };
+  write!(
+    stream,
+    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+    status, CONTENT_TYPE, body.len(), body
+  )?;
+  Ok(())
+}
+
+fn render(series: &Series, now: DateTime<FixedOffset>) -> String {
+  let mut families: BTreeMap<String, Vec<&Sample>> = BTreeMap::new();
+  for sample in series.values().filter(|s| s.kind == Kind::Sum || (now - s.timestamp).num_seconds() < STALE_AFTER_SECONDS) {
+    families.entry(family_name(sample)).or_default().push(sample);
+  }
+  let mut out = String::new();
+  for (name, samples) in families {
+    writeln!(out, "# HELP {} {} ({})", name, escape_help(&samples[0].name), escape_help(&samples[0].unit)).unwrap();
+    writeln!(out, "# TYPE {} {}", name, match samples[0].kind { Kind::Gauge => "gauge", Kind::Sum | Kind::Cumulative => "counter" }).unwrap();
+    for sample in samples {
+      let labels: Vec<String> = sample.dimensions.iter()
+        .map(|d| format!("{}=\"{}\"", label_name(&d.name), escape_label_value(&d.value)))
+        .collect();
+      if labels.is_empty() {
+        writeln!(out, "{} {}", name, sample.value).unwrap();
+      } else {
+        writeln!(out, "{}{{{}}} {}", name, labels.join(","), sample.value).unwrap();
+      }
+    }
+  }
+  out
+}
+
+fn family_name(sample: &Sample) -> String {
+  let name = metric_name(&sample.name, &sample.unit);
+  match sample.kind {
+    Kind::Sum | Kind::Cumulative if !name.ends_with("_total") => format!("{}_total", name),
+    _ => name,
+  }
+}
+
+pub fn metric_name(name: &str, unit: &str) -> String {
+  let suffix = match unit {
+    "Count" | "None" => String::new(),
+    "Count/Second" => String::from("per_second"),
+    unit => unit.to_lowercase().replace('/', "_per_"),
+  };
+  let mut metric = sanitize(name, true);
+  if !suffix.is_empty() && !metric.ends_with(&suffix) {
+    metric.push('_');
+    metric.push_str(&suffix);
+  }
+  metric
+}
+
+pub fn label_name(name: &str) -> String {
+  sanitize(name, false)
+}
+
+fn sanitize(name: &str, allow_colon: bool) -> String {
+  let mut sanitized: String = name.chars()
+    .map(|c| if c.is_ascii_alphanumeric() || c == '_' || (allow_colon && c == ':') { c } else { '_' })
+    .collect();
+  if sanitized.chars().next().is_none_or(|c| c.is_ascii_digit()) {
+    sanitized.insert(0, '_');
+  }
+  sanitized
+}
+
+fn escape_help(text: &str) -> String {
+  text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+  escape_help(value).replace('"', "\\\"")
+}
@@ -0,0 +1,54 @@
+use crate::metadata_v2::{Metric, Stats};
+use chrono::{DateTime, FixedOffset};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Default)]
+pub struct RateTracker {
+  previous: HashMap<(String, String), (DateTime<FixedOffset>, f64)>,
+}
+
+impl RateTracker {
+  pub fn new() -> RateTracker {
+    RateTracker { previous: HashMap::new() }
+  }
+
+  pub fn update(&mut self, stats: &mut [Stats]) {
+    for s in stats.iter_mut() {
+      let timestamp = s.timestamp;
+      for counter in s.counters.drain(..) {
+        let key = (s.container_id.clone(), counter.name.clone());
+        let previous = self.previous.insert(key, (timestamp, counter.value));
+        let (previous_timestamp, previous_value) = match previous {
+          Some(previous) => previous,
+          None => continue,
+        };
+        let elapsed = (timestamp - previous_timestamp).num_milliseconds() as f64 / 1000.0;
+        if elapsed <= 0.0 {
+          continue;
+        }
+        if counter.value < previous_value {
+          debug!("Counter {} on {} was reset, skipping one sample", counter.name, s.container_id);
+          continue;
+        }
+        s.metrics.push(Metric {
+          unit: rate_unit(&counter.unit),
+          value: (counter.value - previous_value) / elapsed,
+          name: counter.name,
+        });
+      }
+    }
+    let live: HashSet<&String> = stats.iter().map(|s| &s.container_id).collect();
+    self.previous.retain(|(container_id, _), _| live.contains(container_id));
+  }
+}
+
+fn rate_unit(unit: &str) -> String {
+  match unit {
+    "Bytes" => String::from("Bytes/Second"),
+    "Kilobytes" => String::from("Kilobytes/Second"),
+    "Megabytes" => String::from("Megabytes/Second"),
+    "Bits" => String::from("Bits/Second"),
+    _ => String::from("Count/Second"),
+  }
+}
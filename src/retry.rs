@@ -0,0 +1,102 @@
+use failure::Error;
+use log::{debug, warn};
+use rand::Rng;
+use std::cmp::min;
+use std::thread::sleep;
+use std::time::Duration;
+
+pub fn with_backoff<T, F>(retries: usize, initial_delay: Duration, mut action: F) -> Result<T, Error>
+    where F: FnMut() -> Result<T, Error> {
+  let mut delay = initial_delay;
+  let mut attempt = 0;
+  loop {
+    match action() {
+      Ok(result) => return Ok(result),
+      Err(err) if attempt < retries => {
+        debug!("Attempt {} failed, retrying in {:?}: {}", attempt + 1, delay, err);
+        sleep(delay);
+        delay *= 2;
+        attempt += 1;
+      },
+      Err(err) => return Err(err),
+    }
+  }
+}
+
+pub struct FailureTolerance {
+  consecutive: usize,
+  max_consecutive: usize,
+}
+
+impl FailureTolerance {
+  pub fn new(max_consecutive: usize) -> FailureTolerance {
+    FailureTolerance { consecutive: 0, max_consecutive }
+  }
+
+  pub fn check<T>(&mut self, result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+      Ok(value) => {
+        self.consecutive = 0;
+        Ok(Some(value))
+      },
+      Err(err) => {
+        self.consecutive += 1;
+        if self.max_consecutive > 0 && self.consecutive >= self.max_consecutive {
+          return Err(err);
+        }
+        warn!("Failure {} in a row, tolerating: {}", self.consecutive, err);
+        Ok(None)
+      },
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+  pub initial_delay: Duration,
+  pub max_delay: Duration,
+}
+
+impl Backoff {
+  pub fn ceiling(&self, attempt: u32) -> Duration {
+    let factor = 2u32.saturating_pow(min(attempt, 16));
+    min(self.initial_delay.checked_mul(factor).unwrap_or(self.max_delay), self.max_delay)
+  }
+
+  pub fn delay(&self, attempt: u32) -> Duration {
+    self.jittered(attempt, rand::thread_rng().gen_range(0.0, 1.0))
+  }
+
+  pub fn jittered(&self, attempt: u32, fraction: f64) -> Duration {
+    let ceiling = self.ceiling(attempt);
+    let nanos = ceiling.as_secs() as f64 * 1e9 + f64::from(ceiling.subsec_nanos());
+    Duration::from_nanos((nanos * fraction.clamp(0.0, 1.0)) as u64)
+  }
+}
+
+pub struct RetryBudget {
+  capacity: f64,
+  tokens: f64,
+}
+
+impl RetryBudget {
+  pub fn new(capacity: usize) -> RetryBudget {
+    RetryBudget { capacity: capacity as f64, tokens: capacity as f64 }
+  }
+
+  pub fn tokens(&self) -> f64 {
+    self.tokens
+  }
+
+  pub fn withdraw(&mut self, cost: f64) -> bool {
+    if self.tokens < cost {
+      return false;
+    }
+    self.tokens -= cost;
+    true
+  }
+
+  pub fn deposit(&mut self, amount: f64) {
+    self.tokens = (self.tokens + amount).min(self.capacity);
+  }
+}
@@ -0,0 +1,222 @@
+use crate::overflow::{Overflow, OverflowPolicy};
+use crate::sample::{Sample, Samples};
+use crate::shutdown;
+use crate::sink::{self, Sink};
+use crate::spool::{Spool, SpoolLimits};
+use failure::Error;
+use log::{error, info, warn};
+use std::cmp::min;
+use std::iter;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+const MAX_SKIPPED_INTERVALS: usize = 8;
+
+pub struct Route {
+  sink: Box<dyn Sink + Send>,
+  queue: Samples,
+  queue_size: usize,
+  overflow: Overflow,
+  batch_size: usize,
+  consecutive_failures: u32,
+  skip_intervals: usize,
+  spool: Option<Spool>,
+}
+
+impl Route {
+  pub fn new(sink: Box<dyn Sink + Send>, queue_size: usize, batch_size: Option<usize>) -> Route {
+    Route {
+      sink,
+      queue: Samples::new(),
+      queue_size,
+      overflow: Overflow::default(),
+      batch_size: batch_size.unwrap_or(usize::MAX),
+      consecutive_failures: 0,
+      skip_intervals: 0,
+      spool: None,
+    }
+  }
+
+  pub fn with_overflow(mut self, policy: OverflowPolicy) -> Route {
+    self.overflow = Overflow::new(policy);
+    self
+  }
+
+  pub fn with_spool(mut self, dir: &Path, name: &str, limits: SpoolLimits) -> Result<Route, Error> {
+    let (spool, mut replayed) = Spool::open(dir, name, limits)?;
+    replayed.append(&mut self.queue);
+    self.queue = replayed;
+    self.spool = Some(spool);
+    Ok(self)
+  }
+
+  pub fn spawn(self) -> Result<Worker, Error> {
+    let name = self.name().to_owned();
+    let (sender, receiver) = channel();
+    let thread = thread::Builder::new()
+      .name(format!("sink-{}", name))
+      .spawn(move || self.run(&receiver))?;
+    Ok(Worker { name, sender, thread })
+  }
+
+  pub fn name(&self) -> &str {
+    self.sink.name()
+  }
+
+  pub fn queued(&self) -> usize {
+    self.queue.len()
+  }
+
+  pub fn push(&mut self, samples: &[Sample]) {
+    let queued = self.queue.len();
+    for sample in samples {
+      match self.sink.validate(sample) {
+        Ok(()) => self.queue.push(sample.clone()),
+        Err(cause) => error!("Sink {} cannot accept {:?}, dropping it: {}", self.sink.name(), sample, cause),
+      }
+    }
+    let over_limit = match self.spool.as_mut() {
+      Some(spool) => match spool.append(&self.queue[queued..]) {
+        Ok(()) => spool.over_limit(),
+        Err(err) => {
+          warn!("Could not spool metrics for sink {} to {}: {}", self.sink.name(), spool.path().display(), err);
+          false
+        },
+      },
+      None => false,
+    };
+    if over_limit {
+      self.persist();
+    }
+  }
+
+  pub fn flush(&mut self) -> Result<usize, Error> {
+    let queued = self.queue.len();
+    let flushed = self.flush_queue();
+    if self.queue.len() != queued {
+      self.persist();
+    }
+    flushed
+  }
+
+  pub fn flush_until(&mut self, deadline: Instant) -> bool {
+    let sink = &mut self.sink;
+    let batch_size = self.batch_size;
+    let flushed = shutdown::flush_until(deadline, &mut self.queue, |samples| {
+      sink::deliver_in_batches(sink.as_mut(), samples, batch_size, Some(deadline))
+    });
+    self.persist();
+    flushed
+  }
+
+  fn run(mut self, receiver: &Receiver<Delivery>) -> bool {
+    while let Ok(delivery) = receiver.recv() {
+      let mut containers = 0;
+      let mut deadline = None;
+      for delivery in iter::once(delivery).chain(receiver.try_iter()) {
+        match delivery {
+          Delivery::Report(samples, count) => {
+            self.push(&samples);
+            containers = count;
+          },
+          Delivery::Shutdown(samples, until) => {
+            self.push(&samples);
+            deadline = Some(until);
+          },
+        }
+      }
+      if let Some(deadline) = deadline {
+        let flushed = self.flush_until(deadline);
+        if !flushed {
+          warn!("Could not flush all metrics to {}", self.name());
+        }
+        return flushed;
+      }
+      let queued = self.queued();
+      match self.flush() {
+        Ok(sent) => info!("Reported {}/{} metrics to {} on {} containers", sent, queued, self.name(), containers),
+        Err(err) => error!("Sink {} failed with {} metrics queued: {}", self.name(), self.queued(), err),
+      }
+    }
+    false
+  }
+
+  fn flush_queue(&mut self) -> Result<usize, Error> {
+    if self.skip_intervals > 0 {
+      self.skip_intervals -= 1;
+      crate::maintain_queue(&mut self.queue, self.queue_size, &mut self.overflow, Box::new(|_: &Samples| Ok(0)))?;
+      info!("Sink {} is backing off with {} metrics queued", self.sink.name(), self.queue.len());
+      return Ok(0);
+    }
+    let sink = &mut self.sink;
+    let batch_size = self.batch_size;
+    let mut sent = 0;
+    let mut pending = 0;
+    let mut failure = None;
+    crate::maintain_queue(&mut self.queue, self.queue_size, &mut self.overflow, Box::new(|samples: &Samples| {
+      pending = samples.len();
+      let (acknowledged, err) = sink::deliver_in_batches(sink.as_mut(), samples, batch_size, None);
+      sent = acknowledged;
+      failure = err;
+      Ok(acknowledged)
+    }))?;
+    if let Some(err) = failure {
+      self.back_off();
+      return Err(err);
+    }
+    if sent < pending {
+      self.back_off();
+    } else {
+      self.consecutive_failures = 0;
+    }
+    Ok(sent)
+  }
+
+  fn back_off(&mut self) {
+    self.consecutive_failures += 1;
+    self.skip_intervals = min(2usize.saturating_pow(self.consecutive_failures - 1) - 1, MAX_SKIPPED_INTERVALS);
+    warn!(
+      "Sink {} failed {} times in a row, skipping {} intervals",
+      self.sink.name(), self.consecutive_failures, self.skip_intervals
+    );
+  }
+
+  fn persist(&mut self) {
+    if let Some(spool) = self.spool.as_mut() {
+      if let Err(err) = spool.rewrite(&self.queue) {
+        warn!("Could not rewrite spool {} for sink {}: {}", spool.path().display(), self.sink.name(), err);
+      }
+    }
+  }
+}
+
+enum Delivery {
+  Report(Samples, usize),
+  Shutdown(Samples, Instant),
+}
+
+pub struct Worker {
+  name: String,
+  sender: Sender<Delivery>,
+  thread: JoinHandle<bool>,
+}
+
+impl Worker {
+  pub fn report(&self, samples: &[Sample], containers: usize) {
+    if self.sender.send(Delivery::Report(samples.to_vec(), containers)).is_err() {
+      error!("Sink {} has stopped, dropping {} metrics", self.name, samples.len());
+    }
+  }
+
+  pub fn shutdown(&self, samples: &[Sample], deadline: Instant) {
+    if self.sender.send(Delivery::Shutdown(samples.to_vec(), deadline)).is_err() {
+      error!("Sink {} has stopped, dropping {} metrics", self.name, samples.len());
+    }
+  }
+
+  pub fn join(self) -> bool {
+    self.thread.join().unwrap_or(false)
+  }
+}
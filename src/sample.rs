@@ -0,0 +1,109 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use crate::metadata_v2;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dimension {
+  pub name: String,
+  pub value: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Kind {
+  #[default]
+  Gauge,
+  Sum,
+  Cumulative,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Statistics {
+  pub sample_count: f64,
+  pub sum: f64,
+  pub minimum: f64,
+  pub maximum: f64,
+}
+
+impl Statistics {
+  pub fn of(sample: &Sample) -> Statistics {
+    sample.statistics.unwrap_or(Statistics {
+      sample_count: 1.0,
+      sum: sample.value,
+      minimum: sample.value,
+      maximum: sample.value,
+    })
+  }
+
+  pub fn merge(&self, other: &Statistics) -> Statistics {
+    Statistics {
+      sample_count: self.sample_count + other.sample_count,
+      sum: self.sum + other.sum,
+      minimum: self.minimum.min(other.minimum),
+      maximum: self.maximum.max(other.maximum),
+    }
+  }
+
+  pub fn average(&self) -> f64 {
+    self.sum / self.sample_count
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+  pub dimensions: Vec<Dimension>,
+  pub distribution: Option<Vec<(f64, f64)>>,
+  pub kind: Kind,
+  pub name: String,
+  pub resource: Vec<Dimension>,
+  pub statistics: Option<Statistics>,
+  pub timestamp: DateTime<FixedOffset>,
+  pub unit: String,
+  pub value: f64,
+}
+
+pub type Samples = Vec<Sample>;
+
+pub fn now() -> DateTime<FixedOffset> {
+  Utc::now().into()
+}
+
+pub fn metrics_from_stats(samples: &mut Samples, stats: Vec<metadata_v2::Stats>, metadata: &HashMap<String, metadata_v2::Metadata>) {
+  stats.into_iter()
+    .filter(|s| metadata.contains_key(&s.container_id))
+    .flat_map(|s| {
+      let container = metadata.get(&s.container_id).unwrap();
+      let dimensions = &container.dimensions;
+      let resource = &container.resource;
+      let timestamp = s.timestamp;
+      let task_cpu = metadata_v2::task_cpu_utilization(&s, container);
+      let gauges = s.metrics.into_iter().chain(task_cpu).map(|m| (Kind::Gauge, m));
+      gauges.chain(s.cumulative.into_iter().map(|m| (Kind::Cumulative, m))).map(move |(kind, m)|
+        Sample {
+          dimensions: dimensions.clone(),
+          kind,
+          name: m.name,
+          resource: resource.clone(),
+          distribution: None,
+          statistics: None,
+          timestamp,
+          unit: m.unit,
+          value: m.value,
+        }
+      )
+    })
+    .for_each(|s| samples.push(s));
+}
+
+pub fn scrape_failure(samples: &mut Samples, timestamp: DateTime<FixedOffset>) {
+  samples.push(Sample {
+    dimensions: Vec::new(),
+    kind: Kind::Sum,
+    name: String::from("scrape_failures"),
+    resource: Vec::new(),
+    distribution: None,
+    statistics: None,
+    timestamp,
+    unit: String::from("Count"),
+    value: 1.0,
+  });
+}
@@ -0,0 +1,87 @@
+use failure::Error;
+use log::{info, warn};
+use signal_hook::{SIGINT, SIGTERM};
+use std::cmp::min;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Default)]
+pub struct Shutdown {
+  requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+  pub fn new() -> Shutdown {
+    Shutdown::default()
+  }
+
+  pub fn register() -> Result<Shutdown, Error> {
+    let shutdown = Shutdown::new();
+    for signal in &[SIGTERM, SIGINT] {
+      signal_hook::flag::register(*signal, shutdown.requested.clone())?;
+    }
+    Ok(shutdown)
+  }
+
+  pub fn request(&self) {
+    self.requested.store(true, Ordering::SeqCst);
+  }
+
+  pub fn requested(&self) -> bool {
+    self.requested.load(Ordering::SeqCst)
+  }
+
+  pub fn sleep(&self, duration: Duration) -> bool {
+    self.sleep_until(Instant::now() + duration)
+  }
+
+  pub fn sleep_until(&self, deadline: Instant) -> bool {
+    while !self.requested() {
+      let now = Instant::now();
+      if now >= deadline {
+        return false;
+      }
+      sleep(min(deadline - now, POLL_INTERVAL));
+    }
+    true
+  }
+}
+
+pub fn next_tick(previous: Instant, period: Duration, now: Instant) -> Instant {
+  let next = previous + period;
+  if next > now {
+    return next;
+  }
+  if period.as_nanos() == 0 {
+    return now;
+  }
+  let missed = ((now - next).as_nanos() / period.as_nanos()) as u32 + 1;
+  warn!("Fell behind by {} ticks of {:?}, skipping them", missed, period);
+  next + period * missed
+}
+
+pub fn flush_until<T, F>(deadline: Instant, queue: &mut Vec<T>, mut flush: F) -> bool
+    where F: FnMut(&Vec<T>) -> (usize, Option<Error>) {
+  while !queue.is_empty() {
+    let now = Instant::now();
+    if now >= deadline {
+      warn!("Flush deadline passed with {} metrics still queued", queue.len());
+      return false;
+    }
+    let (sent, failure) = flush(queue);
+    queue.drain(..min(sent, queue.len()));
+    if let Some(err) = failure {
+      warn!("Giving up flush with {} metrics still queued: {}", queue.len(), err);
+      return false;
+    }
+    if sent == 0 {
+      sleep(min(deadline - now, POLL_INTERVAL));
+    }
+  }
+  info!("Flushed all queued metrics");
+  true
+}
@@ -0,0 +1,169 @@
+use crate::sample::Sample;
+use failure::{Error, format_err};
+use log::{debug, error, warn};
+use reqwest::StatusCode;
+use std::cmp::min;
+use std::str::FromStr;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SinkKind {
+  CloudWatch,
+  Prometheus,
+  StatsD,
+  DogStatsD,
+  Emf,
+  InfluxDB,
+  Otlp,
+}
+
+impl FromStr for SinkKind {
+  type Err = Error;
+
+  fn from_str(kind: &str) -> Result<SinkKind, Error> {
+    match kind {
+      "cloudwatch" => Ok(SinkKind::CloudWatch),
+      "prometheus" => Ok(SinkKind::Prometheus),
+      "statsd" => Ok(SinkKind::StatsD),
+      "dogstatsd" => Ok(SinkKind::DogStatsD),
+      "emf" => Ok(SinkKind::Emf),
+      "influxdb" => Ok(SinkKind::InfluxDB),
+      "otlp" => Ok(SinkKind::Otlp),
+      _ => Err(format_err!("Unknown sink {}", kind)),
+    }
+  }
+}
+
+impl SinkKind {
+  pub fn name(self) -> &'static str {
+    match self {
+      SinkKind::CloudWatch => "cloudwatch",
+      SinkKind::Prometheus => "prometheus",
+      SinkKind::StatsD => "statsd",
+      SinkKind::DogStatsD => "dogstatsd",
+      SinkKind::Emf => "emf",
+      SinkKind::InfluxDB => "influxdb",
+      SinkKind::Otlp => "otlp",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SinkConfig {
+  pub kind: SinkKind,
+  pub queue_size: Option<usize>,
+  pub batch_size: Option<usize>,
+}
+
+impl FromStr for SinkConfig {
+  type Err = Error;
+
+  fn from_str(spec: &str) -> Result<SinkConfig, Error> {
+    let mut parts = spec.split(',');
+    let mut config = SinkConfig {
+      kind: parts.next().unwrap_or_default().parse()?,
+      queue_size: None,
+      batch_size: None,
+    };
+    for option in parts {
+      let (key, value) = match option.find('=') {
+        Some(i) => (&option[..i], &option[i + 1..]),
+        None => return Err(format_err!("Expected KEY=VALUE in sink option {}", option)),
+      };
+      let value = value.parse().map_err(|_| format_err!("Expected a number for {} in sink {}", key, spec))?;
+      match key {
+        "queue_size" => config.queue_size = Some(value),
+        "batch_size" if value > 0 => config.batch_size = Some(value),
+        "batch_size" => return Err(format_err!("batch_size must be positive in sink {}", spec)),
+        _ => return Err(format_err!("Unknown option {} in sink {}", key, spec)),
+      }
+    }
+    Ok(config)
+  }
+}
+
+pub enum Action {
+  Retry(String),
+  Reject(String),
+  Fail(Error),
+}
+
+pub fn classify_http_response(service: &str, status: StatusCode, body: &str) -> Result<(), Action> {
+  if status.is_success() {
+    Ok(())
+  } else if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+    Err(Action::Retry(format!("{}: {}", status, body)))
+  } else if status == StatusCode::BAD_REQUEST || status == StatusCode::PAYLOAD_TOO_LARGE || status == StatusCode::UNPROCESSABLE_ENTITY {
+    Err(Action::Reject(format!("{}: {}", status, body)))
+  } else {
+    Err(Action::Fail(format_err!("{} rejected write with {}: {}", service, status, body)))
+  }
+}
+
+pub trait Sink {
+  fn name(&self) -> &str;
+
+  fn batch_len(&self, pending: &[Sample]) -> usize;
+
+  fn validate(&self, sample: &Sample) -> Result<(), String> {
+    if sample.value.is_finite() {
+      Ok(())
+    } else {
+      Err(format!("value {} is not finite", sample.value))
+    }
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action>;
+
+  fn send_until(&mut self, batch: &[Sample], _deadline: Instant) -> Result<(), Action> {
+    self.send(batch)
+  }
+}
+
+pub fn deliver(sink: &mut dyn Sink, queue: &[Sample]) -> Result<usize, Error> {
+  match deliver_in_batches(sink, queue, usize::MAX, None) {
+    (acknowledged, None) => Ok(acknowledged),
+    (_, Some(err)) => Err(err),
+  }
+}
+
+pub fn deliver_in_batches(
+  sink: &mut dyn Sink,
+  queue: &[Sample],
+  max_batch: usize,
+  deadline: Option<Instant>
+) -> (usize, Option<Error>) {
+  let mut acknowledged = 0;
+  let mut limit = max_batch;
+  while acknowledged < queue.len() {
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+      warn!("Sink {} ran out of time with {} metrics unsent", sink.name(), queue.len() - acknowledged);
+      break;
+    }
+    let pending = &queue[acknowledged..];
+    let batch_len = min(min(sink.batch_len(pending), limit), pending.len()).max(1);
+    let batch = &pending[..batch_len];
+    let sent = match deadline {
+      Some(deadline) => sink.send_until(batch, deadline),
+      None => sink.send(batch),
+    };
+    match sent {
+      Ok(()) => acknowledged += batch_len,
+      Err(Action::Retry(cause)) => {
+        warn!("Sink {} failed, retrying later: {}", sink.name(), cause);
+        break;
+      },
+      Err(Action::Reject(cause)) if batch_len > 1 => {
+        debug!("Sink {} rejected {} metrics, bisecting: {}", sink.name(), batch_len, cause);
+        limit = batch_len / 2;
+      },
+      Err(Action::Reject(cause)) => {
+        error!("Sink {} rejected {:?}, dropping it: {}", sink.name(), pending[0], cause);
+        acknowledged += 1;
+        limit = max_batch;
+      },
+      Err(Action::Fail(err)) => return (acknowledged, Some(err)),
+    }
+  }
+  (acknowledged, None)
+}
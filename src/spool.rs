@@ -0,0 +1,206 @@
+use chrono::DateTime;
+use crate::sample::{self, Dimension, Kind, Sample, Statistics};
+use failure::{Error, format_err};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_MAX_BYTES: u64 = 50_000_000;
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpoolLimits {
+  pub max_bytes: u64,
+  pub max_age: Duration,
+}
+
+impl Default for SpoolLimits {
+  fn default() -> SpoolLimits {
+    SpoolLimits { max_bytes: DEFAULT_MAX_BYTES, max_age: DEFAULT_MAX_AGE }
+  }
+}
+
+pub struct Spool {
+  limits: SpoolLimits,
+  path: PathBuf,
+}
+
+pub fn path(dir: &Path, name: &str) -> PathBuf {
+  dir.join(format!("{}.spool", name))
+}
+
+impl Spool {
+  pub fn open(dir: &Path, name: &str, limits: SpoolLimits) -> Result<(Spool, Vec<Sample>), Error> {
+    fs::create_dir_all(dir)?;
+    let mut spool = Spool { limits, path: path(dir, name) };
+    let read = spool.read()?;
+    let replayed: Vec<Sample> = spool.retained(&read)?.into_iter().map(|(s, _)| s.clone()).collect();
+    spool.rewrite(&replayed)?;
+    if !replayed.is_empty() {
+      info!("Replaying {} metrics from {}", replayed.len(), spool.path.display());
+    }
+    Ok((spool, replayed))
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub fn append(&mut self, samples: &[Sample]) -> Result<(), Error> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+    let lines: String = samples.iter().map(encode_line).collect();
+    file.write_all(lines.as_bytes())?;
+    file.sync_data()?;
+    Ok(())
+  }
+
+  pub fn over_limit(&self) -> bool {
+    fs::metadata(&self.path).map(|m| m.len() > self.limits.max_bytes).unwrap_or(false)
+  }
+
+  pub fn rewrite(&mut self, samples: &[Sample]) -> Result<(), Error> {
+    let lines: String = self.retained(samples)?.into_iter().map(|(_, line)| line).collect();
+    let temporary = self.path.with_extension("spool.tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temporary, &self.path)?;
+    Ok(())
+  }
+
+  fn retained<'a>(&self, samples: &'a [Sample]) -> Result<Vec<(&'a Sample, String)>, Error> {
+    let oldest = sample::now() - chrono::Duration::from_std(self.limits.max_age)?;
+    let mut retained: Vec<(&Sample, String)> = samples.iter()
+      .filter(|s| s.timestamp >= oldest)
+      .map(|s| (s, encode_line(s)))
+      .collect();
+    let mut size: u64 = retained.iter().map(|(_, line)| line.len() as u64).sum();
+    let mut skip = 0;
+    while size > self.limits.max_bytes && skip < retained.len() {
+      size -= retained[skip].1.len() as u64;
+      skip += 1;
+    }
+    retained.drain(..skip);
+    if retained.len() < samples.len() {
+      warn!(
+        "Dropped {} metrics from {} to stay within spool limits",
+        samples.len() - retained.len(), self.path.display()
+      );
+    }
+    Ok(retained)
+  }
+
+  fn read(&self) -> Result<Vec<Sample>, Error> {
+    let file = match File::open(&self.path) {
+      Ok(file) => file,
+      Err(_) => return Ok(Vec::new()),
+    };
+    let mut samples = Vec::new();
+    let mut corrupt = 0;
+    for line in BufReader::new(file).split(b'\n') {
+      match decode_line(&line?) {
+        Ok(sample) => samples.push(sample),
+        Err(_) => corrupt += 1,
+      }
+    }
+    if corrupt > 0 {
+      warn!("Skipped {} corrupt entries in {}", corrupt, self.path.display());
+    }
+    Ok(samples)
+  }
+}
+
+fn checksum(data: &[u8]) -> u64 {
+  data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn encode_line(sample: &Sample) -> String {
+  let document = encode_sample(sample).to_string();
+  format!("{:016x} {}\n", checksum(document.as_bytes()), document)
+}
+
+fn decode_line(line: &[u8]) -> Result<Sample, Error> {
+  let line = std::str::from_utf8(line)?;
+  let (hash, document) = match line.find(' ') {
+    Some(i) => (&line[..i], &line[i + 1..]),
+    None => return Err(format_err!("Missing checksum")),
+  };
+  if u64::from_str_radix(hash, 16)? != checksum(document.as_bytes()) {
+    return Err(format_err!("Checksum mismatch"));
+  }
+  decode_sample(&serde_json::from_str(document)?)
+}
+
+fn encode_dimensions(dimensions: &[Dimension]) -> Value {
+  Value::Array(dimensions.iter().map(|d| json!([d.name, d.value])).collect())
+}
+
+fn decode_dimensions(value: &Value) -> Result<Vec<Dimension>, Error> {
+  value.as_array().ok_or_else(|| format_err!("Expected dimensions"))?.iter()
+    .map(|pair| match (pair[0].as_str(), pair[1].as_str()) {
+      (Some(name), Some(value)) => Ok(Dimension { name: String::from(name), value: String::from(value) }),
+      _ => Err(format_err!("Expected a dimension pair, got {}", pair)),
+    })
+    .collect()
+}
+
+fn decode_distribution(value: &Value) -> Result<Option<Vec<(f64, f64)>>, Error> {
+  if value.is_null() {
+    return Ok(None);
+  }
+  value.as_array().ok_or_else(|| format_err!("Expected a distribution, got {}", value))?.iter()
+    .map(|pair| match (pair[0].as_f64(), pair[1].as_f64()) {
+      (Some(value), Some(count)) => Ok((value, count)),
+      _ => Err(format_err!("Expected a value and count, got {}", pair)),
+    })
+    .collect::<Result<_, _>>()
+    .map(Some)
+}
+
+fn decode_statistics(value: &Value) -> Result<Option<Statistics>, Error> {
+  if value.is_null() {
+    return Ok(None);
+  }
+  let field = |i: usize| value[i].as_f64().ok_or_else(|| format_err!("Expected statistics, got {}", value));
+  Ok(Some(Statistics { sample_count: field(0)?, sum: field(1)?, minimum: field(2)?, maximum: field(3)? }))
+}
+
+fn encode_sample(sample: &Sample) -> Value {
+  json!({
+    "dimensions": encode_dimensions(&sample.dimensions),
+    "distribution": sample.distribution,
+    "kind": match sample.kind { Kind::Gauge => "gauge", Kind::Sum => "sum", Kind::Cumulative => "cumulative" },
+    "name": sample.name,
+    "resource": encode_dimensions(&sample.resource),
+    "statistics": sample.statistics.map(|s| json!([s.sample_count, s.sum, s.minimum, s.maximum])),
+    "timestamp": sample.timestamp.to_rfc3339(),
+    "unit": sample.unit,
+    "value": sample.value,
+  })
+}
+
+fn decode_sample(document: &Value) -> Result<Sample, Error> {
+  let string = |field: &str| document[field].as_str().ok_or_else(|| format_err!("Expected {} in {}", field, document));
+  Ok(Sample {
+    dimensions: decode_dimensions(&document["dimensions"])?,
+    distribution: decode_distribution(&document["distribution"])?,
+    kind: match string("kind")? {
+      "gauge" => Kind::Gauge,
+      "sum" => Kind::Sum,
+      "cumulative" => Kind::Cumulative,
+      kind => return Err(format_err!("Unknown kind {}", kind)),
+    },
+    name: String::from(string("name")?),
+    resource: decode_dimensions(&document["resource"])?,
+    statistics: decode_statistics(&document["statistics"])?,
+    timestamp: DateTime::parse_from_rfc3339(string("timestamp")?)?,
+    unit: String::from(string("unit")?),
+    value: document["value"].as_f64().ok_or_else(|| format_err!("Expected value in {}", document))?,
+  })
+}
@@ -0,0 +1,99 @@
+use crate::sample::Sample;
+use crate::sink::{Action, Sink};
+use failure::{Error, format_err};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flavor {
+  StatsD,
+  DogStatsD,
+}
+
+pub struct StatsdSink {
+  flavor: Flavor,
+  max_packet: usize,
+  prefix: String,
+  socket: UdpSocket,
+}
+
+impl StatsdSink {
+  pub fn new(address: &str, flavor: Flavor, prefix: &str, max_packet: usize) -> Result<StatsdSink, Error> {
+    let target = address.to_socket_addrs()?.next()
+      .ok_or_else(|| format_err!("StatsD address {} did not resolve", address))?;
+    let local: SocketAddr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse()?;
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(target)?;
+    Ok(StatsdSink { flavor, max_packet, prefix: String::from(prefix), socket })
+  }
+
+  fn line(&self, sample: &Sample) -> String {
+    format_line(sample, self.flavor, &self.prefix)
+  }
+}
+
+impl Sink for StatsdSink {
+  fn name(&self) -> &str {
+    match self.flavor {
+      Flavor::StatsD => "statsd",
+      Flavor::DogStatsD => "dogstatsd",
+    }
+  }
+
+  fn batch_len(&self, pending: &[Sample]) -> usize {
+    let mut packet_len = 0;
+    let mut count = 0;
+    for sample in pending {
+      let line_len = self.line(sample).len() + if count > 0 { 1 } else { 0 };
+      if count > 0 && packet_len + line_len > self.max_packet {
+        break;
+      }
+      packet_len += line_len;
+      count += 1;
+    }
+    count
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action> {
+    let packet = batch.iter().map(|sample| self.line(sample)).collect::<Vec<_>>().join("\n");
+    self.socket.send(packet.as_bytes())
+      .map(|_| ())
+      .map_err(|err| Action::Retry(format!("{}", err)))
+  }
+}
+
+pub fn format_line(sample: &Sample, flavor: Flavor, prefix: &str) -> String {
+  let mut path: Vec<String> = Vec::new();
+  let prefix = prefix.trim_end_matches('.');
+  if !prefix.is_empty() {
+    path.push(String::from(prefix));
+  }
+  if flavor == Flavor::StatsD {
+    path.extend(sample.dimensions.iter().map(|d| sanitize(&d.value)));
+  }
+  path.push(sanitize(&sample.name));
+  let mut tags = String::new();
+  if flavor == Flavor::DogStatsD && !sample.dimensions.is_empty() {
+    let pairs: Vec<String> = sample.dimensions.iter()
+      .map(|d| format!("{}:{}", sanitize_tag(&d.name), sanitize_tag(&d.value)))
+      .collect();
+    tags = format!("|#{}", pairs.join(","));
+  }
+  let gauge = |value: f64| format!("{}:{}|g{}", path.join("."), if value == 0.0 { 0.0 } else { value }, tags);
+  if flavor == Flavor::StatsD && sample.value < 0.0 {
+    format!("{}\n{}", gauge(0.0), gauge(sample.value))
+  } else {
+    gauge(sample.value)
+  }
+}
+
+fn sanitize(name: &str) -> String {
+  name.chars()
+    .map(|c| if c.is_whitespace() || c == ':' || c == '|' || c == '@' || c == '#' || c == '.' { '_' } else { c })
+    .collect()
+}
+
+fn sanitize_tag(tag: &str) -> String {
+  tag.chars()
+    .map(|c| if c.is_whitespace() || c == ',' || c == '|' || c == '#' { '_' } else { c })
+    .collect()
+}
@@ -0,0 +1,72 @@
+use chrono::Duration;
+use crate::accumulator::{self, Accumulator};
+use crate::sample::{Kind, Sample, Statistics};
+use super::sample;
+
+fn at_second(second: i64, value: f64) -> Sample {
+  Sample { timestamp: sample().timestamp + Duration::seconds(second), value, ..sample() }
+}
+
+#[test]
+fn reports_statistic_set_per_series() {
+  let mut accumulator = Accumulator::new(false);
+  accumulator.add(&[at_second(0, 10.0), Sample { name: "other".to_owned(), ..at_second(0, 1.0) }]);
+  accumulator.add(&[at_second(5, 90.0)]);
+  accumulator.add(&[at_second(10, 20.0)]);
+  let report = accumulator.report();
+  assert_eq!(2, report.len());
+  assert_eq!(Some(Statistics { sample_count: 3.0, sum: 120.0, minimum: 10.0, maximum: 90.0 }), report[0].statistics);
+  assert_eq!(40.0, report[0].value);
+  assert_eq!(at_second(10, 0.0).timestamp, report[0].timestamp);
+  assert_eq!(None, report[0].distribution);
+  assert_eq!(1.0, report[1].statistics.unwrap().sample_count);
+}
+
+#[test]
+fn report_starts_a_new_period() {
+  let mut accumulator = Accumulator::new(false);
+  accumulator.add(&[at_second(0, 10.0)]);
+  accumulator.report();
+  assert!(accumulator.report().is_empty());
+}
+
+#[test]
+fn sums_are_summed() {
+  let mut accumulator = Accumulator::new(false);
+  let failure = Sample { kind: Kind::Sum, value: 1.0, ..sample() };
+  accumulator.add(std::slice::from_ref(&failure));
+  accumulator.add(&[failure]);
+  assert_eq!(2.0, accumulator.report()[0].value);
+}
+
+#[test]
+fn reports_distribution_of_values() {
+  let mut accumulator = Accumulator::new(true);
+  for value in &[1.0, 2.0, 1.0, 3.0, 1.0] {
+    accumulator.add(&[at_second(0, *value)]);
+  }
+  assert_eq!(Some(vec![(1.0, 3.0), (2.0, 1.0), (3.0, 1.0)]), accumulator.report()[0].distribution);
+}
+
+#[test]
+fn distribution_is_bounded() {
+  let mut accumulator = Accumulator::new(true);
+  for value in 0..200 {
+    accumulator.add(&[at_second(0, f64::from(value))]);
+  }
+  let distribution = accumulator.report()[0].distribution.clone().unwrap();
+  assert_eq!(accumulator::MAX_DISTRIBUTION_VALUES, distribution.len());
+  assert_eq!(200.0, distribution.iter().map(|(_, count)| count).sum::<f64>());
+}
+
+#[test]
+fn non_finite_values_are_skipped() {
+  let mut accumulator = Accumulator::new(true);
+  for value in 0..200 {
+    accumulator.add(&[at_second(0, f64::from(value))]);
+  }
+  accumulator.add(&[at_second(1, f64::NAN), at_second(2, f64::INFINITY)]);
+  let report = accumulator.report();
+  assert_eq!(200.0, report[0].statistics.unwrap().sample_count);
+  assert_eq!(199.0, report[0].statistics.unwrap().maximum);
+}
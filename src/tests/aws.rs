@@ -0,0 +1,37 @@
+use crate::aws::{self, CredentialSource};
+use rusoto_core::Region;
+
+#[test]
+fn named_region() {
+  assert_eq!(Region::EuWest1, aws::region(Some("eu-west-1"), None).unwrap());
+}
+
+#[test]
+fn unknown_region() {
+  assert!(aws::region(Some("moon-north-1"), None).is_err());
+}
+
+#[test]
+fn custom_endpoint_keeps_region_name() {
+  assert_eq!(
+    Region::Custom { name: "eu-west-1".to_owned(), endpoint: "http://localhost:4566".to_owned() },
+    aws::region(Some("eu-west-1"), Some("http://localhost:4566")).unwrap()
+  );
+}
+
+#[test]
+fn custom_endpoint_with_default_region() {
+  match aws::region(None, Some("https://vpce-123.monitoring.eu-west-1.vpce.amazonaws.com")).unwrap() {
+    Region::Custom { endpoint, .. } => assert_eq!("https://vpce-123.monitoring.eu-west-1.vpce.amazonaws.com", endpoint),
+    region => panic!("Expected a custom region, got {:?}", region),
+  }
+}
+
+#[test]
+fn credential_sources() {
+  assert_eq!(CredentialSource::Default, "default".parse().unwrap());
+  assert_eq!(CredentialSource::Container, "container".parse().unwrap());
+  assert_eq!(CredentialSource::Environment, "env".parse().unwrap());
+  assert_eq!(CredentialSource::AssumeRole, "assume-role".parse().unwrap());
+  assert!("instance".parse::<CredentialSource>().is_err());
+}
@@ -1,13 +1,16 @@
-use crate::cloudwatch;
-use rusoto_cloudwatch::CloudWatchClient;
+use crate::cloudwatch::{self, BatchLimits, CloudWatchSink, RetryPolicy};
+use crate::retry::Backoff;
+use crate::sample::{self, Sample, Statistics};
+use crate::sink::{self, Action, Sink};
+use rusoto_cloudwatch::{CloudWatch, CloudWatchClient, Dimension, MetricDatum, StatisticSet};
 use rusoto_core::HttpDispatchError;
 use rusoto_core::param::Params;
 use rusoto_core::signature::{SignedRequest, SignedRequestPayload};
 use rusoto_mock::{MockCredentialsProvider, MockRequestDispatcher};
 use serde_urlencoded;
-use std::iter::repeat;
 use std::sync::{Arc, Mutex};
-use super::metric_datum;
+use std::time::{Duration, Instant};
+use super::sample;
 
 fn client_with_http_status(status: u16) -> CloudWatchClient {
   CloudWatchClient::new_with(
@@ -32,51 +35,157 @@ fn client_with_checker<F>(checker: F) -> CloudWatchClient where F: Fn(Params) +
   )
 }
 
+#[test]
+fn sample_to_metric_datum() {
+  let expected = MetricDatum {
+    dimensions: Some(vec![
+      Dimension {
+        name: "container".to_owned(),
+        value: "ze-id".to_owned()
+      }
+    ]),
+    metric_name: "max_usage".to_owned(),
+    timestamp: Some("2019-01-07T23:15:48.677+00:00".to_owned()),
+    unit: Some("Bytes".to_owned()),
+    value: Some(25.0),
+    ..Default::default()
+  };
+  assert_eq!(expected, cloudwatch::datum_from_sample(&sample()));
+}
+
+#[test]
+fn high_resolution_datum_is_rounded_to_the_second() {
+  let datum = cloudwatch::high_resolution_datum_from_sample(&sample());
+  assert_eq!(Some(1), datum.storage_resolution);
+  assert_eq!(Some("2019-01-07T23:15:49.000+00:00".to_owned()), datum.timestamp);
+  assert_eq!(Some(25.0), datum.value);
+}
+
+#[test]
+fn high_resolution_is_configured_per_metric() {
+  let cw = CloudWatchSink::new(client_with_http_status(200), "testing").with_high_resolution(&["usage".to_owned()]);
+  assert_eq!(None, cw.datum(&sample()).storage_resolution);
+  assert_eq!(Some(1), cw.datum(&Sample { name: "usage".to_owned(), ..sample() }).storage_resolution);
+}
+
+#[test]
+fn compacted_sample_becomes_statistic_set() {
+  let statistics = Statistics { sample_count: 3.0, sum: 60.0, minimum: 10.0, maximum: 30.0 };
+  let datum = cloudwatch::datum_from_sample(&Sample { statistics: Some(statistics), value: 20.0, ..sample() });
+  assert_eq!(None, datum.value);
+  assert_eq!(Some(StatisticSet { sample_count: 3.0, sum: 60.0, minimum: 10.0, maximum: 30.0 }), datum.statistic_values);
+}
+
+#[test]
+fn distribution_becomes_values_and_counts() {
+  let statistics = Statistics { sample_count: 3.0, sum: 40.0, minimum: 10.0, maximum: 20.0 };
+  let datum = cloudwatch::datum_from_sample(&Sample {
+    distribution: Some(vec![(10.0, 2.0), (20.0, 1.0)]),
+    statistics: Some(statistics),
+    ..sample()
+  });
+  assert_eq!((None, None), (datum.value, datum.statistic_values));
+  assert_eq!(Some(vec![10.0, 20.0]), datum.values);
+  assert_eq!(Some(vec![2.0, 1.0]), datum.counts);
+}
+
 #[test]
 fn posts_metric_data_to_cloudwatch() {
-  let cw = client_with_checker(|params: Params| {
+  let mut cw = CloudWatchSink::new(client_with_checker(|params: Params| {
     assert_eq!(params.get("Namespace"), Some(&Some("testing".to_owned())));
     assert_eq!(params.get("MetricData.member.1.Value"), Some(&Some("25".to_owned())));
-  });
-  let mut data = vec![metric_datum()];
-  cloudwatch::report_to_cloudwatch(&cw, "testing", &mut data).unwrap();
+  }), "testing");
+  let data = vec![sample()];
+  sink::deliver(&mut cw, &data).unwrap();
 }
 
 #[test]
-fn sends_batches_of_20_metrics() {
+fn sends_batches_of_max_datums() {
   let count = Arc::new(Mutex::new(0));
   let copy = count.clone();
-  let cw = client_with_checker(move |params: Params| {
+  let limits = BatchLimits { max_datums: 20, ..Default::default() };
+  let mut cw = CloudWatchSink::with_limits(client_with_checker(move |params: Params| {
     assert_eq!(params.get("Namespace"), Some(&Some("testing".to_owned())));
     assert_eq!(params.get("MetricData.member.20.Value"), Some(&Some("25".to_owned())));
+    assert_eq!(params.get("MetricData.member.21.Value"), None);
     *count.lock().unwrap() += 1;
-  });
-  let data = repeat(metric_datum()).take(40).collect();
-  cloudwatch::report_to_cloudwatch(&cw, "testing", &data).unwrap();
+  }), "testing", limits);
+  let data = vec![sample(); 40];
+  sink::deliver(&mut cw, &data).unwrap();
   assert_eq!(2, *copy.lock().unwrap());
 }
 
+#[test]
+fn default_batches_up_to_1000_datums() {
+  let cw = CloudWatchSink::new(client_with_http_status(200), "testing");
+  assert_eq!(1000, cw.batch_len(&vec![sample(); 1001]));
+  assert_eq!(999, cw.batch_len(&vec![sample(); 999]));
+}
+
+fn request_size(namespace: &str, samples: &[Sample]) -> usize {
+  cloudwatch::request_overhead(namespace) + samples.iter().enumerate()
+    .map(|(i, s)| cloudwatch::datum_size(i + 1, &cloudwatch::datum_from_sample(s)))
+    .sum::<usize>()
+}
+
+#[test]
+fn batch_exactly_at_byte_limit() {
+  let data = vec![sample(); 3];
+  let limits = BatchLimits { max_bytes: request_size("testing", &data[..2]), ..Default::default() };
+  let cw = CloudWatchSink::with_limits(client_with_http_status(200), "testing", limits);
+  assert_eq!(2, cw.batch_len(&data));
+}
+
+#[test]
+fn batch_one_byte_below_limit() {
+  let data = vec![sample(); 3];
+  let limits = BatchLimits { max_bytes: request_size("testing", &data[..2]) - 1, ..Default::default() };
+  let cw = CloudWatchSink::with_limits(client_with_http_status(200), "testing", limits);
+  assert_eq!(1, cw.batch_len(&data));
+}
+
+#[test]
+fn oversized_datum_is_sent_alone() {
+  let limits = BatchLimits { max_bytes: 10, ..Default::default() };
+  let cw = CloudWatchSink::with_limits(client_with_http_status(200), "testing", limits);
+  assert_eq!(1, cw.batch_len(&vec![sample(); 3]));
+}
+
+#[test]
+fn estimated_size_matches_request_body() {
+  let data = vec![sample(), Sample { name: "usage/total bytes".to_owned(), ..sample() }];
+  let expected = request_size("testing", &data);
+  let dispatcher = MockRequestDispatcher::with_status(200).with_request_checker(move |req: &SignedRequest|
+    if let Some(SignedRequestPayload::Buffer(ref buffer)) = req.payload {
+      assert!(buffer.len() <= expected, "{} > {}", buffer.len(), expected);
+      assert!(buffer.len() + 64 > expected, "{} is a poor estimate for {}", expected, buffer.len());
+    }
+  );
+  let mut cw = CloudWatchSink::new(CloudWatchClient::new_with(dispatcher, MockCredentialsProvider, Default::default()), "testing");
+  assert_eq!(2, sink::deliver(&mut cw, &data).unwrap());
+}
+
 #[test]
 fn says_count_items_were_sent() {
-  let cw = client_with_http_status(200);
-  let data = vec![metric_datum(), metric_datum()];
-  assert_eq!(2, cloudwatch::report_to_cloudwatch(&cw, "testing", &data).unwrap());
+  let mut cw = CloudWatchSink::new(client_with_http_status(200), "testing");
+  let data = vec![sample(), sample()];
+  assert_eq!(2, sink::deliver(&mut cw, &data).unwrap());
 }
 
 #[test]
 fn says_zero_items_were_processed_on_dispatch_error() {
-  let cw = CloudWatchClient::new_with(
+  let mut cw = CloudWatchSink::new(CloudWatchClient::new_with(
     MockRequestDispatcher::with_dispatch_error(HttpDispatchError::new("boom!".to_owned())),
     MockCredentialsProvider,
     Default::default()
-  );
-  let data = vec![metric_datum()];
-  assert_eq!(0, cloudwatch::report_to_cloudwatch(&cw, "testing", &data).unwrap());
+  ), "testing");
+  let data = vec![sample()];
+  assert_eq!(0, sink::deliver(&mut cw, &data).unwrap());
 }
 
 #[test]
 fn cloudwatch_server_side_error_is_readable() {
-  let cw = CloudWatchClient::new_with(
+  let mut cw = CloudWatchSink::new(CloudWatchClient::new_with(
     MockRequestDispatcher::with_status(400).with_body(
       r#"<ErrorResponse xmlns="http://monitoring.amazonaws.com/doc/2010-08-01/">
       <Error>
@@ -89,10 +198,143 @@ fn cloudwatch_server_side_error_is_readable() {
     ),
     MockCredentialsProvider,
     Default::default()
-  );
-  let mut data = vec![metric_datum()];
-  match cloudwatch::report_to_cloudwatch(&cw, "testing", &mut data) {
+  ), "testing");
+  let data = vec![sample()];
+  match sink::deliver(&mut cw, &data) {
     Ok(_) => panic!("Expected failed request to return err"),
     Err(msg) => assert!(format!("{}", msg).contains("some message")),
   };
 }
+
+const THROTTLED: &str = r#"<ErrorResponse xmlns="http://monitoring.amazonaws.com/doc/2010-08-01/">
+  <Error>
+    <Type>Sender</Type>
+    <Code>Throttling</Code>
+    <Message>Rate exceeded</Message>
+  </Error>
+  <RequestId>uuid</RequestId>
+</ErrorResponse>"#;
+
+fn counting_client(dispatcher: MockRequestDispatcher, count: Arc<Mutex<usize>>) -> CloudWatchClient {
+  CloudWatchClient::new_with(
+    dispatcher.with_request_checker(move |_: &SignedRequest| *count.lock().unwrap() += 1),
+    MockCredentialsProvider,
+    Default::default()
+  )
+}
+
+fn fast_retries(retries: u32, budget: usize) -> RetryPolicy {
+  RetryPolicy {
+    retries,
+    backoff: Backoff { initial_delay: Duration::from_millis(1), max_delay: Duration::from_millis(2) },
+    budget,
+  }
+}
+
+#[test]
+fn throttling_is_detected() {
+  let client = CloudWatchClient::new_with(
+    MockRequestDispatcher::with_status(400).with_body(THROTTLED),
+    MockCredentialsProvider,
+    Default::default()
+  );
+  let err = client.put_metric_data(Default::default()).sync().unwrap_err();
+  assert!(cloudwatch::is_throttling(&err));
+  match cloudwatch::classify_cloudwatch_error(err) {
+    Action::Retry(cause) => assert!(cause.starts_with("Throttled")),
+    _ => panic!("Expected throttling to be retried"),
+  }
+}
+
+#[test]
+fn retries_within_interval() {
+  let count = Arc::new(Mutex::new(0));
+  let client = counting_client(MockRequestDispatcher::with_status(400).with_body(THROTTLED), count.clone());
+  let mut cw = CloudWatchSink::new(client, "testing").with_retry(fast_retries(2, 10));
+  assert_eq!(0, sink::deliver(&mut cw, &[sample()]).unwrap());
+  assert_eq!(3, *count.lock().unwrap());
+  assert_eq!(6.0, cw.retry_budget());
+}
+
+#[test]
+fn retries_stop_at_deadline() {
+  let count = Arc::new(Mutex::new(0));
+  let client = counting_client(MockRequestDispatcher::with_status(400).with_body(THROTTLED), count.clone());
+  let retry = RetryPolicy {
+    retries: 2,
+    backoff: Backoff { initial_delay: Duration::from_secs(5), max_delay: Duration::from_secs(5) },
+    budget: 10,
+  };
+  let mut cw = CloudWatchSink::new(client, "testing").with_retry(retry);
+  let start = Instant::now();
+  assert!(cw.send_until(&[sample()], start + Duration::from_millis(100)).is_err());
+  assert!(start.elapsed() < Duration::from_secs(1));
+  assert_eq!(1, *count.lock().unwrap());
+}
+
+#[test]
+fn retry_budget_limits_attempts() {
+  let count = Arc::new(Mutex::new(0));
+  let client = counting_client(
+    MockRequestDispatcher::with_dispatch_error(HttpDispatchError::new("boom!".to_owned())),
+    count.clone()
+  );
+  let mut cw = CloudWatchSink::new(client, "testing").with_retry(fast_retries(5, 2));
+  assert_eq!(0, sink::deliver(&mut cw, &[sample()]).unwrap());
+  assert_eq!(3, *count.lock().unwrap());
+  assert_eq!(0, sink::deliver(&mut cw, &[sample()]).unwrap());
+  assert_eq!(4, *count.lock().unwrap());
+}
+
+#[test]
+fn success_earns_back_budget() {
+  let mut cw = CloudWatchSink::new(client_with_http_status(200), "testing").with_retry(fast_retries(2, 1));
+  assert_eq!(1, sink::deliver(&mut cw, &[sample()]).unwrap());
+  assert_eq!(1.0, cw.retry_budget());
+}
+
+#[test]
+fn failures_are_not_retried() {
+  let count = Arc::new(Mutex::new(0));
+  let client = counting_client(
+    MockRequestDispatcher::with_status(400).with_body(
+      "<ErrorResponse><Error><Code>MissingParameter</Code><Message>bad</Message></Error></ErrorResponse>"
+    ),
+    count.clone()
+  );
+  let mut cw = CloudWatchSink::new(client, "testing").with_retry(fast_retries(2, 10));
+  assert!(sink::deliver(&mut cw, &[sample()]).is_err());
+  assert_eq!(1, *count.lock().unwrap());
+}
+
+#[test]
+fn invalid_values_are_rejected_for_bisection() {
+  let count = Arc::new(Mutex::new(0));
+  let client = counting_client(
+    MockRequestDispatcher::with_status(400).with_body(
+      "<ErrorResponse><Error><Code>InvalidParameterValue</Code><Message>bad</Message></Error></ErrorResponse>"
+    ),
+    count.clone()
+  );
+  let mut cw = CloudWatchSink::new(client, "testing").with_retry(fast_retries(2, 10));
+  assert_eq!(4, sink::deliver(&mut cw, &vec![sample(); 4]).unwrap());
+  assert_eq!(8, *count.lock().unwrap());
+}
+
+#[test]
+fn validates_samples_locally() {
+  let cw = CloudWatchSink::new(client_with_http_status(200), "testing");
+  assert!(cw.validate(&sample()).is_ok());
+  assert!(cw.validate(&Sample { value: 0.0, ..sample() }).is_ok());
+  assert!(cw.validate(&Sample { value: -1e100, ..sample() }).is_ok());
+  assert!(cw.validate(&Sample { value: f64::NAN, ..sample() }).is_err());
+  assert!(cw.validate(&Sample { value: f64::NEG_INFINITY, ..sample() }).is_err());
+  assert!(cw.validate(&Sample { value: 1e110, ..sample() }).is_err());
+  assert!(cw.validate(&Sample { value: -1e-110, ..sample() }).is_err());
+  assert!(cw.validate(&Sample { name: "x".repeat(256), ..sample() }).is_err());
+  assert!(cw.validate(&Sample { name: "x".repeat(255), ..sample() }).is_ok());
+  let long_dimension = sample::Dimension { name: "container".to_owned(), value: "x".repeat(1025) };
+  assert!(cw.validate(&Sample { dimensions: vec![long_dimension], ..sample() }).is_err());
+  let empty_dimension = sample::Dimension { name: "container".to_owned(), value: "".to_owned() };
+  assert!(cw.validate(&Sample { dimensions: vec![empty_dimension], ..sample() }).is_err());
+}
@@ -1,4 +1,10 @@
+use crate::aws::{AwsSettings, CredentialSource};
+use crate::cloudwatch::BatchLimits;
 use crate::config;
+use crate::metadata_v2::NetworkReporting;
+use crate::metric_spec;
+use crate::overflow::OverflowPolicy;
+use crate::sink::{SinkConfig, SinkKind};
 use std::time::Duration;
 
 fn with_mandatory(mut extra: Vec<String>) -> Vec<String> {
@@ -24,7 +30,7 @@ fn help_option() {
 fn minimum_configuration() {
   let args = with_mandatory(Vec::<String>::new());
   if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
-    assert_eq!("http://169.254.170.2", res.base_url);
+    assert_eq!(None, res.base_url);
     assert_eq!(1, res.log_level);
     assert_eq!("some-namespace", res.namespace);
   } else {
@@ -42,6 +48,12 @@ fn set_interval() {
   }
 }
 
+#[test]
+fn zero_interval_is_rejected() {
+  let args = with_mandatory(vec!["-i".to_owned(), "0".to_owned()]);
+  assert!(config::parse_args(&args).is_err());
+}
+
 #[test]
 fn info_log_level() {
   let args = with_mandatory(vec!["-l".to_owned(), "2".to_owned()]);
@@ -60,3 +72,277 @@ fn print_help() {
     panic!("Expected a RunMode::Normal");
   }
 }
+
+#[test]
+fn network_per_interface() {
+  let args = with_mandatory(vec!["--network-per-interface".to_owned()]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(NetworkReporting::PerInterface, res.network_reporting);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn default_metrics() {
+  let args = with_mandatory(Vec::<String>::new());
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(metric_spec::default_specs(), res.metrics);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn metrics_from_command_line() {
+  let args = with_mandatory(vec![
+    "-m".to_owned(), "/pids_stats/current,pids,Count".to_owned(),
+    "-m".to_owned(), "/memory_stats/usage,usage,Bytes".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(vec!["pids", "usage"], res.metrics.iter().map(|m| m.name.as_str()).collect::<Vec<&str>>());
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn invalid_metric_from_command_line() {
+  let args = with_mandatory(vec!["-m".to_owned(), "/pids_stats/current,pids".to_owned()]);
+  assert!(config::parse_args(&args).is_err());
+}
+
+#[test]
+fn dimensions_from_command_line() {
+  let args = with_mandatory(vec!["-d".to_owned(), "family=Family".to_owned(), "-d".to_owned(), "task=TaskId".to_owned()]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(vec!["family", "task"], res.dimensions.iter().map(|d| d.name.as_str()).collect::<Vec<&str>>());
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn scrape_failure_tolerance() {
+  let args = with_mandatory(vec!["--scrape-retries".to_owned(), "0".to_owned(), "--max-scrape-failures".to_owned(), "3".to_owned()]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(0, res.scrape_retries);
+    assert_eq!(3, res.max_scrape_failures);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn set_shutdown_deadline() {
+  let args = with_mandatory(vec!["--shutdown-deadline".to_owned(), "25".to_owned()]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(Duration::from_secs(25), res.shutdown_deadline);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn default_sink_is_cloudwatch() {
+  let args = with_mandatory(Vec::<String>::new());
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(vec![SinkKind::CloudWatch], sink_kinds(&res));
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn multiple_sinks() {
+  let args = with_mandatory(vec![
+    "-s".to_owned(), "prometheus".to_owned(),
+    "-s".to_owned(), "cloudwatch".to_owned(),
+    "--prometheus-listen".to_owned(), "127.0.0.1:9100".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(vec![SinkKind::Prometheus, SinkKind::CloudWatch], sink_kinds(&res));
+    assert_eq!("127.0.0.1:9100", res.prometheus_listen);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn unknown_sink() {
+  let args = with_mandatory(vec!["-s".to_owned(), "carrier-pigeon".to_owned()]);
+  assert!(config::parse_args(&args).is_err());
+}
+
+#[test]
+fn statsd_sink() {
+  let args = with_mandatory(vec![
+    "-s".to_owned(), "dogstatsd".to_owned(),
+    "--statsd-address".to_owned(), "localhost:8125".to_owned(),
+    "--statsd-prefix".to_owned(), "ecs.app".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(vec![SinkKind::DogStatsD], sink_kinds(&res));
+    assert_eq!("localhost:8125", res.statsd_address);
+    assert_eq!("ecs.app", res.statsd_prefix);
+    assert_eq!(1432, res.statsd_max_packet);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn influx_sink() {
+  let args = with_mandatory(vec![
+    "-s".to_owned(), "influxdb".to_owned(),
+    "--influx-org".to_owned(), "ze-org".to_owned(),
+    "--influx-bucket".to_owned(), "stats".to_owned(),
+    "--influx-token".to_owned(), "s3cr3t".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(vec![SinkKind::InfluxDB], sink_kinds(&res));
+    assert_eq!("http://localhost:8086", res.influx_url);
+    assert_eq!(Some("ze-org".to_owned()), res.influx_org);
+    assert_eq!(Some("stats".to_owned()), res.influx_bucket);
+    assert_eq!(Some(config::Secret("s3cr3t".to_owned())), res.influx_token);
+    assert!(!format!("{:?}", res).contains("s3cr3t"));
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+fn sink_kinds(configuration: &config::Configuration) -> Vec<SinkKind> {
+  configuration.sinks.iter().map(|s| s.kind).collect()
+}
+
+#[test]
+fn sink_with_own_queue_and_batch_size() {
+  let args = with_mandatory(vec![
+    "-s".to_owned(), "statsd,queue_size=1000,batch_size=50".to_owned(),
+    "-s".to_owned(), "cloudwatch".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(vec![
+      SinkConfig { kind: SinkKind::StatsD, queue_size: Some(1000), batch_size: Some(50) },
+      SinkConfig { kind: SinkKind::CloudWatch, queue_size: None, batch_size: None },
+    ], res.sinks);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn invalid_sink_options() {
+  for spec in &["statsd,queue", "statsd,queue_size=many", "statsd,batch_size=0", "statsd,colour=blue"] {
+    let args = with_mandatory(vec!["-s".to_owned(), spec.to_string()]);
+    assert!(config::parse_args(&args).is_err(), "{} should be rejected", spec);
+  }
+}
+
+#[test]
+fn duplicate_sinks() {
+  let args = with_mandatory(vec!["-s".to_owned(), "emf".to_owned(), "-s".to_owned(), "emf,batch_size=5".to_owned()]);
+  assert!(config::parse_args(&args).is_err());
+}
+
+#[test]
+fn cloudwatch_batch_limits() {
+  let args = with_mandatory(vec![
+    "--cloudwatch-max-datums".to_owned(), "500".to_owned(),
+    "--cloudwatch-max-bytes".to_owned(), "40000".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(BatchLimits { max_datums: 500, max_bytes: 40000 }, res.cloudwatch_limits);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn cloudwatch_retry_policy() {
+  let args = with_mandatory(vec![
+    "--cloudwatch-retries".to_owned(), "4".to_owned(),
+    "--cloudwatch-retry-budget".to_owned(), "50".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(4, res.cloudwatch_retry.retries);
+    assert_eq!(50, res.cloudwatch_retry.budget);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn assume_role_settings() {
+  let args = with_mandatory(vec![
+    "--region".to_owned(), "eu-west-1".to_owned(),
+    "--cloudwatch-endpoint".to_owned(), "http://localhost:4566".to_owned(),
+    "--credentials".to_owned(), "assume-role".to_owned(),
+    "--role-arn".to_owned(), "arn:aws:iam::111122223333:role/metrics".to_owned(),
+    "--external-id".to_owned(), "ze-external-id".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(AwsSettings {
+      credentials: CredentialSource::AssumeRole,
+      endpoint: Some("http://localhost:4566".to_owned()),
+      external_id: Some("ze-external-id".to_owned()),
+      region: Some("eu-west-1".to_owned()),
+      role_arn: Some("arn:aws:iam::111122223333:role/metrics".to_owned()),
+      session_name: "fargate-stats-reporter".to_owned(),
+    }, res.aws);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn assume_role_requires_role_arn() {
+  let args = with_mandatory(vec!["--credentials".to_owned(), "assume-role".to_owned()]);
+  assert!(config::parse_args(&args).is_err());
+}
+
+#[test]
+fn queue_overflow_policy() {
+  let args = with_mandatory(vec!["--queue-overflow".to_owned(), "keep-extremes".to_owned()]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(OverflowPolicy::KeepExtremes, res.queue_overflow);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn unknown_queue_overflow_policy() {
+  let args = with_mandatory(vec!["--queue-overflow".to_owned(), "drop-everything".to_owned()]);
+  assert!(config::parse_args(&args).is_err());
+}
+
+#[test]
+fn sample_interval() {
+  let args = with_mandatory(vec!["--sample-interval".to_owned(), "5".to_owned(), "--sample-distribution".to_owned()]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(Some(Duration::from_secs(5)), res.sample_interval);
+    assert!(res.sample_distribution);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
+
+#[test]
+fn sample_interval_cannot_exceed_interval() {
+  let args = with_mandatory(vec!["-i".to_owned(), "10".to_owned(), "--sample-interval".to_owned(), "20".to_owned()]);
+  assert!(config::parse_args(&args).is_err());
+}
+
+#[test]
+fn high_resolution_metrics() {
+  let args = with_mandatory(vec![
+    "--high-resolution".to_owned(), "usage".to_owned(),
+    "--high-resolution".to_owned(), "cpu_usage".to_owned(),
+  ]);
+  if let config::RunMode::Normal(res) = config::parse_args(&args).unwrap() {
+    assert_eq!(vec!["usage".to_owned(), "cpu_usage".to_owned()], res.high_resolution);
+  } else {
+    panic!("Expected a RunMode::Normal");
+  }
+}
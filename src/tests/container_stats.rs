@@ -1,4 +1,5 @@
 use crate::metadata_v2;
+use crate::metric_spec::default_specs;
 use chrono::DateTime;
 use mockito::mock;
 use reqwest::Client as HttpClient;
@@ -16,7 +17,7 @@ fn no_containers() {
     .create();
 
   assert_eq!(
-    metadata_v2::container_stats(&http, &mockito::server_url()).unwrap(),
+    metadata_v2::container_stats(&http, &mockito::server_url(), &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap(),
     Vec::<metadata_v2::Stats>::new()
   );
 }
@@ -43,6 +44,8 @@ fn extrace_container_stats() {
   let expected = vec![
     metadata_v2::Stats {
       container_id: "ze-id".to_owned(),
+      counters: vec![],
+      cumulative: vec![],
       metrics: vec![metadata_v2::Metric {
         name: "max_usage".to_owned(),
         unit: "Bytes".to_owned(),
@@ -55,7 +58,7 @@ fn extrace_container_stats() {
       timestamp: DateTime::parse_from_rfc3339("2019-01-07T23:15:48.677482816Z").unwrap(),
     }
   ];
-  let actual = metadata_v2::container_stats(&http, &mockito::server_url()).unwrap();
+  let actual = metadata_v2::container_stats(&http, &mockito::server_url(), &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
   assert_eq!(expected, actual);
 }
 
@@ -71,7 +74,250 @@ fn new_container_is_null() {
     .create();
 
   assert_eq!(
-    metadata_v2::container_stats(&http, &mockito::server_url()).unwrap(),
+    metadata_v2::container_stats(&http, &mockito::server_url(), &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap(),
     Vec::<metadata_v2::Stats>::new()
   );
 }
+
+#[test]
+fn extract_cpu_stats() {
+  let http = HttpClient::new();
+  let reply = json!({
+    "cpu-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "max_usage": 0.25,
+        "usage": 0.12,
+      },
+      "cpu_stats": {
+        "cpu_usage": {"total_usage": 1_500_000_000u64, "percpu_usage": [0, 0]},
+        "system_cpu_usage": 24_000_000_000u64,
+        "throttling_data": {"periods": 120, "throttled_periods": 3, "throttled_time": 25_000_000}
+      },
+      "precpu_stats": {
+        "cpu_usage": {"total_usage": 1_000_000_000u64},
+        "system_cpu_usage": 22_000_000_000u64
+      }
+    }
+  });
+
+  let _stats_api = mock("GET", "/v2/stats")
+    .with_status(200)
+    .with_header("content-type", "application/json")
+    .with_body(reply.to_string())
+    .create();
+
+  let actual = metadata_v2::container_stats(&http, &mockito::server_url(), &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
+  assert_eq!(&actual[0].metrics[2..], &[
+    metadata_v2::Metric {
+      name: "cpu_core_utilization".to_owned(),
+      unit: "Percent".to_owned(),
+      value: 50.0,
+    },
+  ]);
+  assert_eq!(actual[0].cumulative, vec![
+    metadata_v2::Metric {
+      name: "throttled_periods".to_owned(),
+      unit: "Count".to_owned(),
+      value: 3.0,
+    },
+    metadata_v2::Metric {
+      name: "throttled_time".to_owned(),
+      unit: "Microseconds".to_owned(),
+      value: 25000.0,
+    },
+  ]);
+}
+
+#[test]
+fn first_sample_has_no_cpu_utilization() {
+  let http = HttpClient::new();
+  let reply = json!({
+    "first-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "max_usage": 0.25,
+        "usage": 0.12,
+      },
+      "cpu_stats": {
+        "cpu_usage": {"total_usage": 1_500_000_000u64},
+        "system_cpu_usage": 24_000_000_000u64,
+        "online_cpus": 2
+      },
+      "precpu_stats": {
+        "cpu_usage": {"total_usage": 0}
+      }
+    }
+  });
+
+  let _stats_api = mock("GET", "/v2/stats")
+    .with_status(200)
+    .with_header("content-type", "application/json")
+    .with_body(reply.to_string())
+    .create();
+
+  let actual = metadata_v2::container_stats(&http, &mockito::server_url(), &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
+  assert_eq!(2, actual[0].metrics.len());
+}
+
+fn network_reply() -> serde_json::Value {
+  json!({
+    "net-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "max_usage": 0.25,
+        "usage": 0.12,
+      },
+      "networks": {
+        "eth0": {"rx_bytes": 100, "rx_packets": 2, "rx_errors": 0, "rx_dropped": 0,
+                 "tx_bytes": 200, "tx_packets": 3, "tx_errors": 0, "tx_dropped": 1},
+        "eth1": {"rx_bytes": 1000, "rx_packets": 20, "rx_errors": 1, "rx_dropped": 0,
+                 "tx_bytes": 2000, "tx_packets": 30, "tx_errors": 0, "tx_dropped": 0}
+      }
+    }
+  })
+}
+
+#[test]
+fn network_counters_summed_over_interfaces() {
+  let actual = metadata_v2::parse_container_stats(&network_reply(), &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
+  let counters = &actual[0].counters;
+  assert_eq!(8, counters.len());
+  assert_eq!(metadata_v2::Metric {
+    name: "network_rx_bytes".to_owned(),
+    unit: "Bytes".to_owned(),
+    value: 1100.0,
+  }, counters[0]);
+  assert_eq!(metadata_v2::Metric {
+    name: "network_tx_dropped".to_owned(),
+    unit: "Count".to_owned(),
+    value: 1.0,
+  }, counters[7]);
+}
+
+#[test]
+fn network_counters_per_interface() {
+  let actual = metadata_v2::parse_container_stats(&network_reply(), &default_specs(), metadata_v2::NetworkReporting::PerInterface).unwrap();
+  let counters = &actual[0].counters;
+  assert_eq!(16, counters.len());
+  assert!(counters.contains(&metadata_v2::Metric {
+    name: "network_eth1_tx_packets".to_owned(),
+    unit: "Count".to_owned(),
+    value: 30.0,
+  }));
+}
+
+#[test]
+fn blkio_counters_summed_over_devices() {
+  let reply = json!({
+    "disk-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "max_usage": 0.25,
+        "usage": 0.12,
+      },
+      "blkio_stats": {
+        "io_service_bytes_recursive": [
+          {"major": 202, "minor": 26368, "op": "Read", "value": 4096},
+          {"major": 202, "minor": 26368, "op": "Write", "value": 8192},
+          {"major": 202, "minor": 26368, "op": "Total", "value": 12288},
+          {"major": 202, "minor": 26400, "op": "Read", "value": 1024},
+        ],
+        "io_serviced_recursive": [
+          {"major": 202, "minor": 26368, "op": "read", "value": 3},
+          {"major": 202, "minor": 26368, "op": "write", "value": 5},
+        ]
+      }
+    }
+  });
+  let actual = metadata_v2::parse_container_stats(&reply, &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
+  let values: Vec<(&str, f64)> = actual[0].counters.iter().map(|c| (c.name.as_str(), c.value)).collect();
+  assert_eq!(vec![
+    ("blkio_read_bytes", 5120.0),
+    ("blkio_write_bytes", 8192.0),
+    ("blkio_read_ops", 3.0),
+    ("blkio_write_ops", 5.0),
+  ], values);
+}
+
+#[test]
+fn empty_or_missing_blkio_stats() {
+  let reply = json!({
+    "empty-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "max_usage": 0.25,
+        "usage": 0.12,
+      },
+      "blkio_stats": {
+        "io_service_bytes_recursive": [],
+        "io_serviced_recursive": null
+      }
+    },
+    "missing-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "max_usage": 0.25,
+        "usage": 0.12,
+      }
+    }
+  });
+  let actual = metadata_v2::parse_container_stats(&reply, &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
+  assert_eq!(2, actual.len());
+  assert!(actual.iter().all(|s| s.counters.is_empty()));
+}
+
+#[test]
+fn missing_or_non_numeric_metric_is_skipped() {
+  let reply = json!({
+    "odd-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "usage": "lots",
+      }
+    }
+  });
+  let actual = metadata_v2::parse_container_stats(&reply, &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
+  assert_eq!(Vec::<metadata_v2::Metric>::new(), actual[0].metrics);
+}
+
+#[test]
+fn scaled_metric_from_spec() {
+  let reply = json!({
+    "ze-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "usage": 2097152,
+      }
+    }
+  });
+  let specs = vec!["/memory_stats/usage,usage_mb,Megabytes,0.00000095367431640625".parse().unwrap()];
+  let actual = metadata_v2::parse_container_stats(&reply, &specs, metadata_v2::NetworkReporting::Summed).unwrap();
+  assert_eq!(vec![metadata_v2::Metric {
+    name: "usage_mb".to_owned(),
+    unit: "Megabytes".to_owned(),
+    value: 2.0,
+  }], actual[0].metrics);
+}
+
+#[test]
+fn stats_is_not_an_object() {
+  let reply = json!(["ze-id"]);
+  match metadata_v2::parse_container_stats(&reply, &default_specs(), metadata_v2::NetworkReporting::Summed) {
+    Err(metadata_v2::ParseError::UnexpectedType { field, .. }) => assert_eq!("stats", field),
+    other => panic!("Expected UnexpectedType, got {:?}", other),
+  }
+}
+
+#[test]
+fn malformed_container_stats_are_skipped() {
+  let reply = json!({
+    "not-an-object": 17,
+    "no-read": {"memory_stats": {"usage": 1}},
+    "numeric-read": {"read": 1546902948, "memory_stats": {"usage": 1}},
+    "bad-read": {"read": "yesterday", "memory_stats": {"usage": 1}},
+    "ze-id": {"read": "2019-01-07T23:15:48.677482816Z", "memory_stats": {"usage": 1}},
+  });
+  let actual = metadata_v2::parse_container_stats(&reply, &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
+  assert_eq!(vec!["ze-id"], actual.iter().map(|s| s.container_id.as_str()).collect::<Vec<&str>>());
+}
@@ -0,0 +1,64 @@
+use crate::dimension_spec::{self, DimensionSpec, Source};
+use serde_json::json;
+
+#[test]
+fn parse_known_sources() {
+  assert_eq!(DimensionSpec::new("image", Source::Container("/Image".to_owned())), "image=Image".parse().unwrap());
+  assert_eq!(DimensionSpec::new("family", Source::Task("/Family".to_owned())), "family=Family".parse().unwrap());
+  assert_eq!(DimensionSpec::new("task", Source::TaskId), "task=TaskId".parse().unwrap());
+  assert_eq!(DimensionSpec::new("cluster", Source::ClusterName), "cluster=ClusterName".parse().unwrap());
+}
+
+#[test]
+fn parse_pointer_sources() {
+  assert_eq!(
+    DimensionSpec::new("team", Source::Container("/Labels/team".to_owned())),
+    "team=container:/Labels/team".parse().unwrap()
+  );
+  assert_eq!(
+    DimensionSpec::new("type", Source::Task("/LaunchType".to_owned())),
+    "type=task:/LaunchType".parse().unwrap()
+  );
+}
+
+#[test]
+fn rejects_malformed_spec() {
+  assert!("Family".parse::<DimensionSpec>().is_err());
+  assert!("=Family".parse::<DimensionSpec>().is_err());
+  assert!("family=Flamily".parse::<DimensionSpec>().is_err());
+}
+
+#[test]
+fn derived_values_accept_plain_names() {
+  let task = json!({"Cluster": "default", "TaskARN": "arn:aws:ecs:us-east-1:012345678910:task/9781c248"});
+  let container = json!({});
+  assert_eq!(Some("default".to_owned()), DimensionSpec::new("c", Source::ClusterName).extract(&task, &container));
+  assert_eq!(Some("9781c248".to_owned()), DimensionSpec::new("t", Source::TaskId).extract(&task, &container));
+}
+
+#[test]
+fn empty_or_structured_values_are_missing() {
+  let task = json!({"Family": "", "Limits": {"CPU": 0.25}});
+  let container = json!({});
+  assert_eq!(None, "f=Family".parse::<DimensionSpec>().unwrap().extract(&task, &container));
+  assert_eq!(None, "l=task:/Limits".parse::<DimensionSpec>().unwrap().extract(&task, &container));
+  assert_eq!(Some("0.25".to_owned()), "l=task:/Limits/CPU".parse::<DimensionSpec>().unwrap().extract(&task, &container));
+}
+
+#[test]
+fn default_is_container_name_as_task() {
+  assert_eq!(vec![DimensionSpec::new("task", Source::Container("/Name".to_owned()))], dimension_spec::default_specs());
+}
+
+#[test]
+fn cluster_resource_attribute_depends_on_arn() {
+  assert_eq!(
+    ("aws.ecs.cluster.name".to_owned(), "default".to_owned()),
+    dimension_spec::resource_attribute("aws.ecs.cluster.arn", "default".to_owned())
+  );
+  let arn = "arn:aws:ecs:eu-west-1:111122223333:cluster/etl".to_owned();
+  assert_eq!(
+    ("aws.ecs.cluster.arn".to_owned(), arn.clone()),
+    dimension_spec::resource_attribute("aws.ecs.cluster.arn", arn)
+  );
+}
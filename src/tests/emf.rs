@@ -0,0 +1,72 @@
+use crate::emf::{self, EmfSink};
+use crate::sample::{Dimension, Sample};
+use crate::sink::{self, Sink};
+use serde_json::{json, Value};
+use super::sample;
+
+fn named(name: &str, value: f64) -> Sample {
+  Sample { name: name.to_owned(), value, ..sample() }
+}
+
+#[test]
+fn groups_metrics_sharing_dimensions_and_timestamp() {
+  let documents = emf::documents("ns", &[named("max_usage", 25.0), named("usage", 12.0)]);
+  assert_eq!(vec![json!({
+    "_aws": {
+      "Timestamp": 1546902948677i64,
+      "CloudWatchMetrics": [{
+        "Namespace": "ns",
+        "Dimensions": [["container"]],
+        "Metrics": [{"Name": "max_usage", "Unit": "Bytes"}, {"Name": "usage", "Unit": "Bytes"}],
+      }],
+    },
+    "container": "ze-id",
+    "max_usage": 25.0,
+    "usage": 12.0,
+  })], documents);
+}
+
+#[test]
+fn separates_dimension_sets() {
+  let mut other = sample();
+  other.dimensions = vec![Dimension { name: "container".to_owned(), value: "other-id".to_owned() }];
+  let documents = emf::documents("ns", &[sample(), other]);
+  assert_eq!(2, documents.len());
+  assert_eq!(json!("other-id"), documents[0]["container"]);
+  assert_eq!(json!("ze-id"), documents[1]["container"]);
+}
+
+#[test]
+fn repeated_metric_starts_new_document() {
+  assert_eq!(2, emf::documents("ns", &[sample(), sample()]).len());
+}
+
+#[test]
+fn at_most_100_metrics_per_document() {
+  let samples: Vec<Sample> = (0..150).map(|i| named(&format!("metric_{}", i), 1.0)).collect();
+  let documents = emf::documents("ns", &samples);
+  let counts: Vec<usize> = documents.iter()
+    .map(|d| d["_aws"]["CloudWatchMetrics"][0]["Metrics"].as_array().unwrap().len())
+    .collect();
+  assert_eq!(vec![100, 50], counts);
+}
+
+#[test]
+fn at_most_30_dimensions() {
+  let mut wide = sample();
+  wide.dimensions = (0..35).map(|i| Dimension { name: format!("d{}", i), value: "v".to_owned() }).collect();
+  let documents = emf::documents("ns", &[wide]);
+  assert_eq!(30, documents[0]["_aws"]["CloudWatchMetrics"][0]["Dimensions"][0].as_array().unwrap().len());
+  assert!(documents[0].get("d30").is_none());
+}
+
+#[test]
+fn writes_json_lines() {
+  let mut sink = EmfSink::new("ns", Vec::new());
+  assert_eq!(2, sink::deliver(&mut sink, &[sample(), named("usage", 1.0)]).unwrap());
+  assert_eq!("emf", sink.name());
+  let output = String::from_utf8(sink.into_inner()).unwrap();
+  let lines: Vec<Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+  assert_eq!(1, lines.len());
+  assert_eq!(json!(25.0), lines[0]["max_usage"]);
+}
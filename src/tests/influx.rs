@@ -0,0 +1,74 @@
+use crate::influx::{self, InfluxSink};
+use crate::sample::{Dimension, Sample};
+use crate::sink::{self, Sink};
+use flate2::read::GzDecoder;
+use mockito::{mock, Matcher};
+use reqwest::Client as HttpClient;
+use std::io::Read;
+use super::sample;
+
+#[test]
+fn line_has_tags_and_nanosecond_timestamp() {
+  assert_eq!(
+    "max_usage,container=ze-id,unit=Bytes value=25.0 1546902948677482816",
+    influx::line_from_sample(&sample()).unwrap()
+  );
+}
+
+#[test]
+fn line_escapes_special_characters() {
+  let sample = Sample {
+    dimensions: vec![
+      Dimension { name: "task name".to_owned(), value: "a=b,c".to_owned() },
+      Dimension { name: "empty".to_owned(), value: "".to_owned() },
+    ],
+    name: "max usage,total".to_owned(),
+    value: 0.5,
+    ..sample()
+  };
+  assert_eq!(
+    "max\\ usage\\,total,task\\ name=a\\=b\\,c,unit=Bytes value=0.5 1546902948677482816",
+    influx::line_from_sample(&sample).unwrap()
+  );
+}
+
+#[test]
+fn non_finite_values_are_skipped() {
+  assert_eq!(None, influx::line_from_sample(&Sample { value: f64::NAN, ..sample() }));
+}
+
+#[test]
+fn body_is_gzipped() {
+  let mut body = String::new();
+  GzDecoder::new(&influx::compress("some lines").unwrap()[..]).read_to_string(&mut body).unwrap();
+  assert_eq!("some lines", body);
+}
+
+#[test]
+fn writes_with_token_and_gzip() {
+  let write_api = mock("POST", Matcher::Regex("^/api/v2/write".to_owned()))
+    .match_query(Matcher::AllOf(vec![
+      Matcher::UrlEncoded("org".to_owned(), "ze-org".to_owned()),
+      Matcher::UrlEncoded("bucket".to_owned(), "written".to_owned()),
+      Matcher::UrlEncoded("precision".to_owned(), "ns".to_owned()),
+    ]))
+    .match_header("authorization", "Token s3cr3t")
+    .match_header("content-encoding", "gzip")
+    .with_status(204)
+    .expect(1)
+    .create();
+  let mut sink = InfluxSink::new(HttpClient::new(), &mockito::server_url(), "ze-org", "written", Some("s3cr3t".to_owned()));
+  assert_eq!(2, sink::deliver(&mut sink, &[sample(), sample()]).unwrap());
+  write_api.assert();
+}
+
+#[test]
+fn server_errors_are_retried() {
+  let _write_api = mock("POST", Matcher::Regex("^/api/v2/write".to_owned()))
+    .match_query(Matcher::UrlEncoded("bucket".to_owned(), "unavailable".to_owned()))
+    .with_status(503)
+    .create();
+  let mut sink = InfluxSink::new(HttpClient::new(), &mockito::server_url(), "ze-org", "unavailable", None);
+  assert_eq!(0, sink::deliver(&mut sink, &[sample()]).unwrap());
+  assert_eq!("influxdb", sink.name());
+}
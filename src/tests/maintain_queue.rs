@@ -1,42 +1,187 @@
 use failure::Error;
-use rusoto_cloudwatch::MetricDatum;
-use super::metric_datum;
+use crate::overflow::{Overflow, OverflowPolicy};
+use chrono::Duration;
+use crate::sample::{Kind, Sample, Statistics};
+use super::sample;
+
+fn numbered(count: usize) -> Vec<Sample> {
+  (0..count).map(|i| Sample { value: i as f64, ..sample() }).collect()
+}
+
+fn values(queue: &[Sample]) -> Vec<f64> {
+  queue.iter().map(|s| s.value).collect()
+}
 
 #[test]
 fn handles_empty_queue() -> Result<(), Error> {
-  let mut queue = Vec::<MetricDatum>::new();
-  crate::maintain_queue(&mut queue, 10, Box::new(|_: &Vec<MetricDatum>| Ok(0 as usize)))
+  let mut queue = Vec::<Sample>::new();
+  crate::maintain_queue(&mut queue, 10, &mut Overflow::default(), Box::new(|_: &Vec<Sample>| Ok(0 as usize)))
 }
 
 #[test]
 fn culls_queue_according_to_closure() -> Result<(), Error> {
-  let mut queue = vec![metric_datum(), metric_datum(), metric_datum()];
-  crate::maintain_queue(&mut queue, 10, Box::new(|_: &Vec<MetricDatum>| Ok(2 as usize)))?;
+  let mut queue = vec![sample(), sample(), sample()];
+  crate::maintain_queue(&mut queue, 10, &mut Overflow::default(), Box::new(|_: &Vec<Sample>| Ok(2 as usize)))?;
   assert_eq!(1, queue.len());
   Ok(())
 }
 
 #[test]
 fn culls_queue_from_start() -> Result<(), Error> {
-  let mut queue = vec![metric_datum(), metric_datum(), metric_datum()];
-  queue[2].value = Some(26.0);
-  crate::maintain_queue(&mut queue, 10, Box::new(|_: &Vec<MetricDatum>| Ok(2 as usize)))?;
-  assert_eq!(Some(26.0), queue[0].value);
+  let mut queue = vec![sample(), sample(), sample()];
+  queue[2].value = 26.0;
+  crate::maintain_queue(&mut queue, 10, &mut Overflow::default(), Box::new(|_: &Vec<Sample>| Ok(2 as usize)))?;
+  assert_eq!(26.0, queue[0].value);
   Ok(())
 }
 
 #[test]
 fn culls_queue_according_to_max_queue_size() -> Result<(), Error> {
-  let mut queue = vec![metric_datum(), metric_datum(), metric_datum()];
-  crate::maintain_queue(&mut queue, 2, Box::new(|_: &Vec<MetricDatum>| Ok(0 as usize)))?;
+  let mut queue = vec![sample(), sample(), sample()];
+  crate::maintain_queue(&mut queue, 2, &mut Overflow::default(), Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
   assert_eq!(2, queue.len());
   Ok(())
 }
 
 #[test]
 fn culls_queue_according_to_minimum() -> Result<(), Error> {
-  let mut queue = vec![metric_datum(), metric_datum(), metric_datum()];
-  crate::maintain_queue(&mut queue, 2, Box::new(|_: &Vec<MetricDatum>| Ok(1 as usize)))?;
+  let mut queue = vec![sample(), sample(), sample()];
+  crate::maintain_queue(&mut queue, 2, &mut Overflow::default(), Box::new(|_: &Vec<Sample>| Ok(1 as usize)))?;
   assert_eq!(2, queue.len());
   Ok(())
 }
+
+#[test]
+fn drop_oldest_keeps_latest_samples() -> Result<(), Error> {
+  let mut queue = numbered(5);
+  let mut overflow = Overflow::new(OverflowPolicy::DropOldest);
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(vec![3.0, 4.0], values(&queue));
+  Ok(())
+}
+
+#[test]
+fn drop_newest_keeps_earliest_samples() -> Result<(), Error> {
+  let mut queue = numbered(5);
+  let mut overflow = Overflow::new(OverflowPolicy::DropNewest);
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(vec![0.0, 1.0], values(&queue));
+  Ok(())
+}
+
+#[test]
+fn drop_newest_still_culls_processed_samples_from_start() -> Result<(), Error> {
+  let mut queue = numbered(5);
+  let mut overflow = Overflow::new(OverflowPolicy::DropNewest);
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(1 as usize)))?;
+  assert_eq!(vec![1.0, 2.0], values(&queue));
+  Ok(())
+}
+
+#[test]
+fn reservoir_keeps_max_size_in_order() -> Result<(), Error> {
+  let mut queue = numbered(50);
+  let mut overflow = Overflow::new(OverflowPolicy::Reservoir);
+  crate::maintain_queue(&mut queue, 10, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(10, queue.len());
+  assert!(values(&queue).windows(2).all(|pair| pair[0] < pair[1]));
+  Ok(())
+}
+
+#[test]
+fn reservoir_samples_evenly_across_intervals() -> Result<(), Error> {
+  let mut early = 0;
+  for _ in 0..200 {
+    let mut queue = Vec::new();
+    let mut overflow = Overflow::new(OverflowPolicy::Reservoir);
+    for interval in 0..10 {
+      queue.extend(numbered(10).into_iter().map(|s| Sample { value: s.value + 10.0 * interval as f64, ..s }));
+      crate::maintain_queue(&mut queue, 10, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+    }
+    early += queue.iter().filter(|s| s.value < 50.0).count();
+  }
+  assert!(early > 700 && early < 1300, "{} of 2000 samples came from the first half", early);
+  Ok(())
+}
+
+#[test]
+fn keep_extremes_keeps_minimum_and_maximum_per_series() -> Result<(), Error> {
+  let mut queue: Vec<Sample> = [5.0, 9.0, 1.0, 4.0, 6.0].iter()
+    .map(|value| Sample { value: *value, ..sample() })
+    .collect();
+  queue.extend([3.0, 2.0].iter().map(|value| Sample { name: "other".to_owned(), value: *value, ..sample() }));
+  let mut overflow = Overflow::new(OverflowPolicy::KeepExtremes);
+  crate::maintain_queue(&mut queue, 4, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(vec![9.0, 1.0, 3.0, 2.0], values(&queue));
+  Ok(())
+}
+
+#[test]
+fn keep_extremes_drops_oldest_extremes_when_still_over() -> Result<(), Error> {
+  let mut queue: Vec<Sample> = ["a", "b", "c"].iter()
+    .map(|name| Sample { name: name.to_string(), ..sample() })
+    .collect();
+  let mut overflow = Overflow::new(OverflowPolicy::KeepExtremes);
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(vec!["b", "c"], queue.iter().map(|s| s.name.as_str()).collect::<Vec<_>>());
+  Ok(())
+}
+
+fn at_second(second: i64, value: f64) -> Sample {
+  Sample { timestamp: sample().timestamp + Duration::seconds(second), value, ..sample() }
+}
+
+#[test]
+fn compact_folds_oldest_minute_into_statistic_set() -> Result<(), Error> {
+  let mut queue = vec![at_second(0, 4.0), at_second(5, 1.0), at_second(10, 7.0), at_second(60, 2.0), at_second(65, 3.0)];
+  let mut overflow = Overflow::new(OverflowPolicy::Compact);
+  crate::maintain_queue(&mut queue, 3, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(3, queue.len());
+  assert_eq!(Some(Statistics { sample_count: 3.0, sum: 12.0, minimum: 1.0, maximum: 7.0 }), queue[0].statistics);
+  assert_eq!(4.0, queue[0].value);
+  assert_eq!(0, queue[0].timestamp.timestamp() % 60);
+  assert_eq!(vec![None, None], queue[1..].iter().map(|s| s.statistics).collect::<Vec<_>>());
+  Ok(())
+}
+
+#[test]
+fn compact_keeps_series_apart() -> Result<(), Error> {
+  let mut queue = vec![at_second(0, 1.0), Sample { name: "other".to_owned(), ..at_second(1, 2.0) }, at_second(2, 3.0)];
+  let mut overflow = Overflow::new(OverflowPolicy::Compact);
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(vec!["max_usage", "other"], queue.iter().map(|s| s.name.as_str()).collect::<Vec<_>>());
+  assert_eq!(2.0, queue[0].statistics.unwrap().sample_count);
+  Ok(())
+}
+
+#[test]
+fn compact_merges_already_compacted_samples() -> Result<(), Error> {
+  let mut queue = vec![at_second(0, 1.0), at_second(1, 3.0), at_second(2, 5.0)];
+  let mut overflow = Overflow::new(OverflowPolicy::Compact);
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  queue.push(at_second(3, 9.0));
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(2, queue.len());
+  assert_eq!(Some(Statistics { sample_count: 3.0, sum: 9.0, minimum: 1.0, maximum: 5.0 }), queue[0].statistics);
+  Ok(())
+}
+
+#[test]
+fn compact_drops_oldest_when_compaction_is_not_enough() -> Result<(), Error> {
+  let mut queue: Vec<Sample> = (0..4).map(|minute| at_second(60 * minute, minute as f64)).collect();
+  let mut overflow = Overflow::new(OverflowPolicy::Compact);
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(vec![2.0, 3.0], values(&queue));
+  Ok(())
+}
+
+#[test]
+fn compact_sums_sum_samples() -> Result<(), Error> {
+  let failure = |second| Sample { kind: Kind::Sum, value: 1.0, ..at_second(second, 0.0) };
+  let mut queue = vec![failure(0), failure(1), failure(2), at_second(60, 5.0)];
+  let mut overflow = Overflow::new(OverflowPolicy::Compact);
+  crate::maintain_queue(&mut queue, 2, &mut overflow, Box::new(|_: &Vec<Sample>| Ok(0 as usize)))?;
+  assert_eq!(3.0, queue[0].value);
+  assert_eq!(Some(Statistics { sample_count: 3.0, sum: 3.0, minimum: 1.0, maximum: 1.0 }), queue[0].statistics);
+  Ok(())
+}
@@ -0,0 +1,136 @@
+use crate::dimension_spec;
+use crate::metadata::Endpoint;
+use crate::metadata_v2;
+use crate::metadata_v4;
+use crate::metric_spec::default_specs;
+use crate::sample::Dimension;
+use chrono::DateTime;
+use mockito::mock;
+use reqwest::Client as HttpClient;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+
+#[test]
+fn task_metadata_from_v4_task() {
+  let http = HttpClient::new();
+  let reply = json!({
+    "Cluster": "default",
+    "TaskARN": "arn:aws:ecs:eu-west-1:111122223333:task/default/158d1c8083dd49d6b527399fd6414f5c",
+    "Limits": {"CPU": 0.25, "Memory": 512},
+    "Containers": [
+      {
+        "DockerId": "ze-id",
+        "Name": "some-container",
+        "Limits": {"CPU": 10, "Memory": 128},
+        "Networks": [{"NetworkMode": "awsvpc", "IPv4Addresses": ["10.0.2.106"]}]
+      },
+      {
+        "DockerId": "pause-id",
+        "Name": "~internal~ecs~pause",
+      }
+    ]
+  });
+
+  let _metadata_api = mock("GET", "/task")
+    .with_status(200)
+    .with_header("content-type", "application/json")
+    .with_body(reply.to_string())
+    .create();
+
+  let actual = metadata_v4::task_metadata(&http, &mockito::server_url(), &dimension_spec::default_specs()).unwrap();
+  let mut expected = HashMap::<String, metadata_v2::Metadata>::new();
+  expected.insert(
+    "ze-id".to_owned(),
+    metadata_v2::Metadata {
+      container_id: "ze-id".to_owned(),
+      cpu_limit: Some(0.25),
+      dimensions: vec![
+        Dimension {
+          name: "task".to_owned(),
+          value: "some-container".to_owned()
+        }
+      ],
+      resource: vec![
+        Dimension {
+          name: "aws.ecs.cluster.name".to_owned(),
+          value: "default".to_owned()
+        },
+        Dimension {
+          name: "aws.ecs.task.arn".to_owned(),
+          value: "arn:aws:ecs:eu-west-1:111122223333:task/default/158d1c8083dd49d6b527399fd6414f5c".to_owned()
+        },
+        Dimension {
+          name: "container.id".to_owned(),
+          value: "ze-id".to_owned()
+        },
+        Dimension {
+          name: "container.name".to_owned(),
+          value: "some-container".to_owned()
+        }
+      ]
+    }
+  );
+  assert_eq!(expected, actual);
+}
+
+#[test]
+fn container_stats_from_v4_task_stats() {
+  let http = HttpClient::new();
+  let reply = json!({
+    "ze-id": {
+      "read": "2019-01-07T23:15:48.677482816Z",
+      "memory_stats": {
+        "max_usage": 0.25,
+        "usage": 0.12,
+      },
+      "networks": {
+        "eth1": {"rx_bytes": 564, "tx_bytes": 1362}
+      }
+    },
+    "starting-id": null
+  });
+
+  let _stats_api = mock("GET", "/task/stats")
+    .with_status(200)
+    .with_header("content-type", "application/json")
+    .with_body(reply.to_string())
+    .create();
+
+  let actual = metadata_v4::container_stats(&http, &mockito::server_url(), &default_specs(), metadata_v2::NetworkReporting::Summed).unwrap();
+  assert_eq!(1, actual.len());
+  assert_eq!("ze-id", actual[0].container_id);
+  assert_eq!(DateTime::parse_from_rfc3339("2019-01-07T23:15:48.677482816Z").unwrap(), actual[0].timestamp);
+}
+
+#[test]
+fn endpoint_prefers_v4_from_environment() {
+  let v4 = || Endpoint::V4("http://169.254.170.2/v4/ze-id".to_owned(), "http://169.254.170.2".to_owned());
+  env::remove_var("ECS_CONTAINER_METADATA_URI_V4");
+  assert_eq!(Endpoint::V2("http://169.254.170.2".to_owned()), Endpoint::detect(None));
+  assert_eq!(Endpoint::V2("http://localhost:8080".to_owned()), Endpoint::detect(Some("http://localhost:8080")));
+  env::set_var("ECS_CONTAINER_METADATA_URI_V4", "http://169.254.170.2/v4/ze-id/");
+  assert_eq!(v4(), Endpoint::detect(None));
+  assert_eq!(Endpoint::V2("http://localhost:8080".to_owned()), Endpoint::detect(Some("http://localhost:8080")));
+  env::set_var("ECS_CONTAINER_METADATA_URI_V4", "");
+  assert_eq!(Endpoint::V2("http://169.254.170.2".to_owned()), Endpoint::detect(None));
+  env::remove_var("ECS_CONTAINER_METADATA_URI_V4");
+}
+
+#[test]
+fn falls_back_to_v2_when_v4_fails() {
+  let reply = json!({
+    "Cluster": "default",
+    "TaskARN": "arn:aws:ecs:eu-west-1:111122223333:task/default/158d1c8083dd49d6b527399fd6414f5c",
+    "Containers": [{"DockerId": "fallback-id", "Name": "some-container"}]
+  });
+  let _broken_v4 = mock("GET", "/broken-v4/task").with_status(500).create();
+  let _metadata_api = mock("GET", "/fallback/v2/metadata")
+    .with_status(200)
+    .with_header("content-type", "application/json")
+    .with_body(reply.to_string())
+    .create();
+  let endpoint = Endpoint::V4(format!("{}/broken-v4", mockito::server_url()), format!("{}/fallback", mockito::server_url()));
+  let metadata = endpoint.task_metadata(&HttpClient::new(), &dimension_spec::default_specs()).unwrap();
+  assert!(metadata.contains_key("fallback-id"));
+}
@@ -0,0 +1,50 @@
+use crate::metric_spec::{self, MetricSpec};
+use serde_json::json;
+
+#[test]
+fn parse_spec_from_cli() {
+  let spec: MetricSpec = "/memory_stats/stats/cache,cache,Bytes".parse().unwrap();
+  assert_eq!(MetricSpec::new("/memory_stats/stats/cache", "cache", "Bytes", 1.0).unwrap(), spec);
+}
+
+#[test]
+fn parse_spec_with_scale() {
+  let spec: MetricSpec = "/memory_stats/usage, usage, Kilobytes, 0.0009765625".parse().unwrap();
+  assert_eq!(0.0009765625, spec.scale);
+  assert_eq!("Kilobytes", spec.unit);
+}
+
+#[test]
+fn rejects_malformed_spec() {
+  assert!("/memory_stats/usage,usage".parse::<MetricSpec>().is_err());
+  assert!("memory_stats/usage,usage,Bytes".parse::<MetricSpec>().is_err());
+  assert!("/memory_stats/usage,usage,Furlongs".parse::<MetricSpec>().is_err());
+  assert!("/memory_stats/usage,usage,Bytes,lots".parse::<MetricSpec>().is_err());
+}
+
+#[test]
+fn parse_specs_from_json() {
+  let body = json!([
+    {"pointer": "/memory_stats/usage", "name": "usage", "unit": "Bytes"},
+    {"pointer": "/pids_stats/current", "name": "pids", "unit": "Count", "scale": 1},
+  ]);
+  let specs = metric_spec::parse_specs(&body).unwrap();
+  assert_eq!(vec![
+    MetricSpec::new("/memory_stats/usage", "usage", "Bytes", 1.0).unwrap(),
+    MetricSpec::new("/pids_stats/current", "pids", "Count", 1.0).unwrap(),
+  ], specs);
+}
+
+#[test]
+fn rejects_incomplete_json_spec() {
+  assert!(metric_spec::parse_specs(&json!({"pointer": "/memory_stats/usage"})).is_err());
+  assert!(metric_spec::parse_specs(&json!([{"pointer": "/memory_stats/usage", "unit": "Bytes"}])).is_err());
+  assert!(metric_spec::parse_specs(&json!([{"pointer": "/a", "name": "a", "unit": "Bytes", "scale": "x"}])).is_err());
+}
+
+#[test]
+fn extract_missing_value() {
+  let spec = MetricSpec::new("/memory_stats/usage", "usage", "Bytes", 1.0).unwrap();
+  assert_eq!(None, spec.extract(&json!({"memory_stats": {}})));
+  assert_eq!(Some(12.0), spec.extract(&json!({"memory_stats": {"usage": 12}})));
+}
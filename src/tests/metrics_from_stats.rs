@@ -1,12 +1,13 @@
-use crate::cloudwatch;
 use crate::metadata_v2;
+use crate::sample::{self, Dimension, Kind, Sample};
 use chrono::DateTime;
-use rusoto_cloudwatch::{Dimension, MetricDatum};
 use std::collections::HashMap;
 
 fn stats() -> metadata_v2::Stats {
   metadata_v2::Stats {
     container_id: "ze-id".to_owned(),
+    counters: vec![],
+    cumulative: vec![],
     metrics: vec![metadata_v2::Metric {
       name: "max_usage".to_owned(),
       unit: "Bytes".to_owned(),
@@ -17,47 +18,123 @@ fn stats() -> metadata_v2::Stats {
 }
 
 #[test]
-fn stats_to_cw_metrics() {
-  let mut metrics = Vec::<MetricDatum>::new();
+fn stats_to_samples() {
+  let mut samples = Vec::<Sample>::new();
   let stats = vec![stats()];
   let mut metadata = HashMap::<String, metadata_v2::Metadata>::new();
   metadata.insert(
     "ze-id".to_owned(),
     metadata_v2::Metadata {
       container_id: "ze-id".to_owned(),
+      cpu_limit: None,
       dimensions: vec![
         Dimension {
           name: "task".to_owned(),
           value: "some-container".to_owned()
         }
+      ],
+      resource: vec![
+        Dimension {
+          name: "container.name".to_owned(),
+          value: "some-container".to_owned()
+        }
       ]
     }
   );
 
   let expected = vec![
-    MetricDatum {
-      dimensions: Some(vec![
+    Sample {
+      dimensions: vec![
         Dimension {
           name: "task".to_owned(),
           value: "some-container".to_owned()
         }
-      ]),
-      metric_name: "max_usage".to_owned(),
-      timestamp: Some("2019-01-07T23:15:48.677+00:00".to_owned()),
-      unit: Some("Bytes".to_owned()),
-      value: Some(0.25),
-      ..Default::default()
+      ],
+      kind: Kind::Gauge,
+      name: "max_usage".to_owned(),
+      resource: vec![
+        Dimension {
+          name: "container.name".to_owned(),
+          value: "some-container".to_owned()
+        }
+      ],
+      distribution: None,
+      statistics: None,
+      timestamp: DateTime::parse_from_rfc3339("2019-01-07T23:15:48.677482816Z").unwrap(),
+      unit: "Bytes".to_owned(),
+      value: 0.25,
     }
   ];
-  cloudwatch::metrics_from_stats(&mut metrics, stats, &metadata);
-  assert_eq!(expected, metrics);
+  sample::metrics_from_stats(&mut samples, stats, &metadata);
+  assert_eq!(expected, samples);
 }
 
 #[test]
 fn container_is_unknown() {
-  let mut metrics = Vec::<MetricDatum>::new();
+  let mut samples = Vec::<Sample>::new();
   let stats = vec![stats()];
   let metadata = HashMap::<String, metadata_v2::Metadata>::new();
-  cloudwatch::metrics_from_stats(&mut metrics, stats, &metadata);
-  assert_eq!(Vec::<MetricDatum>::new(), metrics);
+  sample::metrics_from_stats(&mut samples, stats, &metadata);
+  assert_eq!(Vec::<Sample>::new(), samples);
+}
+
+#[test]
+fn cpu_utilization_relative_to_task_limit() {
+  let mut samples = Vec::<Sample>::new();
+  let mut stats = stats();
+  stats.metrics = vec![metadata_v2::Metric {
+    name: "cpu_core_utilization".to_owned(),
+    unit: "Percent".to_owned(),
+    value: 20.0
+  }];
+  let mut metadata = HashMap::<String, metadata_v2::Metadata>::new();
+  metadata.insert(
+    "ze-id".to_owned(),
+    metadata_v2::Metadata {
+      container_id: "ze-id".to_owned(),
+      cpu_limit: Some(0.25),
+      dimensions: vec![],
+      resource: vec![]
+    }
+  );
+  sample::metrics_from_stats(&mut samples, vec![stats], &metadata);
+  assert_eq!(2, samples.len());
+  assert_eq!("cpu_utilization", samples[1].name);
+  assert_eq!(80.0, samples[1].value);
+}
+
+#[test]
+fn throttling_counters_are_cumulative() {
+  let mut samples = Vec::<Sample>::new();
+  let mut stats = stats();
+  stats.cumulative = vec![metadata_v2::Metric {
+    name: "throttled_periods".to_owned(),
+    unit: "Count".to_owned(),
+    value: 3.0
+  }];
+  let mut metadata = HashMap::<String, metadata_v2::Metadata>::new();
+  metadata.insert(
+    "ze-id".to_owned(),
+    metadata_v2::Metadata { container_id: "ze-id".to_owned(), cpu_limit: None, dimensions: vec![], resource: vec![] }
+  );
+  sample::metrics_from_stats(&mut samples, vec![stats], &metadata);
+  assert_eq!(vec![Kind::Gauge, Kind::Cumulative], samples.iter().map(|s| s.kind).collect::<Vec<_>>());
+}
+
+#[test]
+fn scrape_failure_is_counted() {
+  let mut samples = Vec::<Sample>::new();
+  let timestamp = DateTime::parse_from_rfc3339("2019-01-07T23:15:48.677482816Z").unwrap();
+  sample::scrape_failure(&mut samples, timestamp);
+  assert_eq!(vec![Sample {
+    dimensions: vec![],
+    kind: Kind::Sum,
+    name: "scrape_failures".to_owned(),
+    resource: vec![],
+    distribution: None,
+    statistics: None,
+    timestamp,
+    unit: "Count".to_owned(),
+    value: 1.0,
+  }], samples);
 }
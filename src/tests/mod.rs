@@ -1,22 +1,42 @@
 extern crate rusoto_mock;
 extern crate serde_urlencoded;
 
+mod accumulator;
+mod aws;
 mod container_stats;
+mod dimension_spec;
+mod emf;
+mod influx;
 mod maintain_queue;
+mod metadata_v4;
+mod metric_spec;
+mod otlp;
+mod prometheus;
+mod rates;
+mod retry;
+mod route;
+mod shutdown;
+mod sink;
+mod spool;
+mod statsd;
 mod metrics_from_stats;
 mod config;
 mod cloudwatch;
 mod task_metadata;
 
-use rusoto_cloudwatch::{Dimension, MetricDatum};
+use chrono::DateTime;
+use crate::sample::{Dimension, Kind, Sample};
 
-fn metric_datum() -> MetricDatum {
-  MetricDatum {
-    dimensions: Some(vec![Dimension { name: "container".to_owned(), value: "ze-id".to_owned() }]),
-    metric_name: "max_usage".to_owned(),
-    timestamp: Some("ze-time".to_owned()),
-    unit: Some("Bytes".to_owned()),
-    value: Some(25.0),
-    ..Default::default()
+fn sample() -> Sample {
+  Sample {
+    dimensions: vec![Dimension { name: "container".to_owned(), value: "ze-id".to_owned() }],
+    kind: Kind::Gauge,
+    name: "max_usage".to_owned(),
+    resource: vec![Dimension { name: "container.id".to_owned(), value: "ze-id".to_owned() }],
+    distribution: None,
+    statistics: None,
+    timestamp: DateTime::parse_from_rfc3339("2019-01-07T23:15:48.677482816Z").unwrap(),
+    unit: "Bytes".to_owned(),
+    value: 25.0,
   }
 }
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Duration};
+use crate::otlp::{self, OtlpSink};
+use crate::sample::{Dimension, Kind, Sample};
+use crate::sink::{self, Sink};
+use mockito::{mock, Matcher};
+use reqwest::Client as HttpClient;
+use serde_json::json;
+use super::sample;
+
+#[test]
+fn gauges_with_resource_attributes() {
+  let request = otlp::export_request(&[sample()], &[None]);
+  assert_eq!(json!({
+    "resourceMetrics": [{
+      "resource": {
+        "attributes": [
+          {"key": "cloud.provider", "value": {"stringValue": "aws"}},
+          {"key": "cloud.platform", "value": {"stringValue": "aws_ecs"}},
+          {"key": "container.id", "value": {"stringValue": "ze-id"}},
+        ],
+      },
+      "scopeMetrics": [{
+        "scope": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")},
+        "metrics": [{
+          "name": "max_usage",
+          "unit": "By",
+          "gauge": {
+            "dataPoints": [{
+              "attributes": [{"key": "container", "value": {"stringValue": "ze-id"}}],
+              "timeUnixNano": "1546902948677482816",
+              "asDouble": 25.0,
+            }],
+          },
+        }],
+      }],
+    }],
+  }), request);
+}
+
+#[test]
+fn sums_are_monotonic_deltas() {
+  let failure = Sample { kind: Kind::Sum, name: "scrape_failures".to_owned(), unit: "Count".to_owned(), ..sample() };
+  let request = otlp::export_request(&[failure], &[Some(1_546_902_888_000_000_000)]);
+  let metric = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0];
+  assert_eq!(json!(1), metric["sum"]["aggregationTemporality"]);
+  assert_eq!(json!(true), metric["sum"]["isMonotonic"]);
+  assert_eq!(json!("1"), metric["unit"]);
+  assert_eq!(json!("1546902888000000000"), metric["sum"]["dataPoints"][0]["startTimeUnixNano"]);
+}
+
+#[test]
+fn sums_start_at_previous_report() {
+  let sink = OtlpSink::new(HttpClient::new(), "http://localhost:4318");
+  let first = Sample { kind: Kind::Sum, name: "scrape_failures".to_owned(), unit: "Count".to_owned(), ..sample() };
+  let second = Sample { timestamp: first.timestamp + Duration::seconds(60), ..first.clone() };
+  let request = sink.request(&[first, second]);
+  let points = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0]["sum"]["dataPoints"];
+  assert!(points[0]["startTimeUnixNano"].is_string());
+  assert_eq!(points[0]["timeUnixNano"], points[1]["startTimeUnixNano"]);
+}
+
+#[test]
+fn cumulative_counters_keep_a_fixed_start() {
+  let sink = OtlpSink::new(HttpClient::new(), "http://localhost:4318");
+  let first = Sample { kind: Kind::Cumulative, name: "throttled_periods".to_owned(), unit: "Count".to_owned(), ..sample() };
+  let second = Sample { timestamp: first.timestamp + Duration::seconds(60), value: 30.0, ..first.clone() };
+  let request = sink.request(&[first, second]);
+  let sum = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0]["sum"];
+  assert_eq!(json!(2), sum["aggregationTemporality"]);
+  assert_eq!(json!(true), sum["isMonotonic"]);
+  assert!(sum["dataPoints"][0]["startTimeUnixNano"].is_string());
+  assert_eq!(sum["dataPoints"][0]["startTimeUnixNano"], sum["dataPoints"][1]["startTimeUnixNano"]);
+}
+
+#[test]
+fn skips_samples_with_unrepresentable_time() {
+  let ancient = Sample { timestamp: DateTime::parse_from_rfc3339("1000-01-01T00:00:00Z").unwrap(), ..sample() };
+  let request = otlp::export_request(&[ancient, sample()], &[None, None]);
+  let points = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0]["gauge"]["dataPoints"];
+  assert_eq!(1, points.as_array().unwrap().len());
+}
+
+#[test]
+fn groups_by_resource_and_metric() {
+  let other = Sample {
+    resource: vec![Dimension { name: "container.id".to_owned(), value: "other-id".to_owned() }],
+    ..sample()
+  };
+  let request = otlp::export_request(&[sample(), other.clone(), sample(), other], &[]);
+  let resources = request["resourceMetrics"].as_array().unwrap();
+  assert_eq!(2, resources.len());
+  let metrics = resources[0]["scopeMetrics"][0]["metrics"].as_array().unwrap();
+  assert_eq!(1, metrics.len());
+  assert_eq!(2, metrics[0]["gauge"]["dataPoints"].as_array().unwrap().len());
+}
+
+#[test]
+fn units_are_ucum() {
+  assert_eq!("By/s", otlp::unit("Bytes/Second"));
+  assert_eq!("1/s", otlp::unit("Count/Second"));
+  assert_eq!("%", otlp::unit("Percent"));
+  assert_eq!("us", otlp::unit("Microseconds"));
+}
+
+#[test]
+fn exports_to_collector() {
+  let collector = mock("POST", "/v1/metrics")
+    .match_header("content-type", "application/json")
+    .match_body(Matcher::Regex("\"name\":\"exported\"".to_owned()))
+    .with_status(200)
+    .expect(1)
+    .create();
+  let mut sink = OtlpSink::new(HttpClient::new(), &format!("{}/", mockito::server_url()));
+  let exported = Sample { name: "exported".to_owned(), ..sample() };
+  assert_eq!(1, sink::deliver(&mut sink, &[exported]).unwrap());
+  assert_eq!("otlp", sink.name());
+  collector.assert();
+}
+
+#[test]
+fn keeps_queue_during_collector_outage() {
+  let _collector = mock("POST", "/v1/metrics")
+    .match_body(Matcher::Regex("\"name\":\"unavailable\"".to_owned()))
+    .with_status(503)
+    .create();
+  let mut sink = OtlpSink::new(HttpClient::new(), &mockito::server_url());
+  let unavailable = Sample { name: "unavailable".to_owned(), ..sample() };
+  assert_eq!(0, sink::deliver(&mut sink, &[unavailable]).unwrap());
+}
@@ -0,0 +1,130 @@
+use crate::prometheus::{self, PrometheusSink};
+use crate::sample::{self, Dimension, Kind, Sample};
+use crate::sink::Sink;
+use chrono::DateTime;
+use reqwest::Client as HttpClient;
+use super::sample;
+
+fn sample_at(name: &str, unit: &str, timestamp: &str, value: f64) -> Sample {
+  Sample {
+    name: name.to_owned(),
+    unit: unit.to_owned(),
+    timestamp: DateTime::parse_from_rfc3339(timestamp).unwrap(),
+    value,
+    ..sample()
+  }
+}
+
+#[test]
+fn metric_names_are_safe() {
+  assert_eq!("usage_bytes", prometheus::metric_name("usage", "Bytes"));
+  assert_eq!("rx_bytes", prometheus::metric_name("rx_bytes", "Bytes"));
+  assert_eq!("network_rx_bytes_bytes_per_second", prometheus::metric_name("network_rx_bytes", "Bytes/Second"));
+  assert_eq!("throttled_periods", prometheus::metric_name("throttled_periods", "Count"));
+  assert_eq!("network_eth0_rx_packets_per_second", prometheus::metric_name("network_eth0_rx_packets", "Count/Second"));
+  assert_eq!("_5xx_rate_percent", prometheus::metric_name("5xx-rate", "Percent"));
+  assert_eq!("task_id", prometheus::label_name("task.id"));
+  assert_eq!("ns:metric", prometheus::metric_name("ns:metric", "None"));
+  assert_eq!("ns_label", prometheus::label_name("ns:label"));
+}
+
+#[test]
+fn renders_help_type_and_labels() {
+  let mut sink = PrometheusSink::new();
+  let mut other = sample();
+  other.dimensions = vec![Dimension { name: "container".to_owned(), value: "other \"id\"".to_owned() }];
+  other.value = 12.5;
+  sink.send(&[sample(), other]).ok().unwrap();
+  assert_eq!(
+    "# HELP max_usage_bytes max_usage (Bytes)\n\
+     # TYPE max_usage_bytes gauge\n\
+     max_usage_bytes{container=\"other \\\"id\\\"\"} 12.5\n\
+     max_usage_bytes{container=\"ze-id\"} 25\n",
+    sink.render_at(sample().timestamp)
+  );
+}
+
+#[test]
+fn keeps_latest_sample_per_series() {
+  let mut sink = PrometheusSink::new();
+  sink.send(&[
+    sample_at("usage", "Bytes", "2019-01-07T23:16:00Z", 2.0),
+    sample_at("usage", "Bytes", "2019-01-07T23:15:00Z", 1.0),
+  ]).ok().unwrap();
+  assert!(sink.render_at(sample().timestamp).contains("usage_bytes{container=\"ze-id\"} 2\n"));
+}
+
+#[test]
+fn forgets_stale_series() {
+  let mut sink = PrometheusSink::new();
+  sink.send(&[sample_at("gone", "Count", "2019-01-07T23:00:00Z", 1.0)]).ok().unwrap();
+  sink.send(&[sample_at("usage", "Bytes", "2019-01-07T23:10:00Z", 1.0)]).ok().unwrap();
+  assert!(!sink.render_at(sample().timestamp).contains("gone"));
+}
+
+#[test]
+fn stops_serving_series_when_sending_stops() {
+  let mut sink = PrometheusSink::new();
+  sink.send(&[sample()]).ok().unwrap();
+  assert!(sink.render_at(sample().timestamp).contains("max_usage_bytes"));
+  assert_eq!("", sink.render());
+}
+
+#[test]
+fn groups_series_that_sanitize_to_the_same_name() {
+  let mut sink = PrometheusSink::new();
+  sink.send(&[
+    sample_at("a.b", "Count", "2019-01-07T23:15:48Z", 1.0),
+    sample_at("a_a", "Count", "2019-01-07T23:15:48Z", 2.0),
+    sample_at("a_b", "Count", "2019-01-07T23:15:48Z", 3.0),
+  ]).ok().unwrap();
+  let rendered = sink.render_at(sample().timestamp);
+  assert_eq!(1, rendered.matches("# TYPE a_b gauge").count());
+  assert_eq!(
+    "# HELP a_a a_a (Count)\n\
+     # TYPE a_a gauge\n\
+     a_a{container=\"ze-id\"} 2\n\
+     # HELP a_b a.b (Count)\n\
+     # TYPE a_b gauge\n\
+     a_b{container=\"ze-id\"} 1\n\
+     a_b{container=\"ze-id\"} 3\n",
+    rendered
+  );
+}
+
+#[test]
+fn sums_are_running_totals() {
+  let mut sink = PrometheusSink::new();
+  let failure = |timestamp| Sample { kind: Kind::Sum, value: 1.0, ..sample_at("scrape_failures", "Count", timestamp, 0.0) };
+  sink.send(&[failure("2019-01-07T23:00:00Z")]).ok().unwrap();
+  sink.send(&[failure("2019-01-07T23:01:00Z"), sample_at("usage", "Bytes", "2019-01-07T23:15:00Z", 1.0)]).ok().unwrap();
+  assert_eq!(
+    "# HELP scrape_failures_total scrape_failures (Count)\n\
+     # TYPE scrape_failures_total counter\n\
+     scrape_failures_total{container=\"ze-id\"} 2\n",
+    sink.render_at(DateTime::parse_from_rfc3339("2019-01-07T23:30:00Z").unwrap())
+  );
+}
+
+#[test]
+fn cumulative_counters_are_counters() {
+  let mut sink = PrometheusSink::new();
+  let throttled = Sample { kind: Kind::Cumulative, ..sample_at("throttled_periods", "Count", "2019-01-07T23:15:48Z", 3.0) };
+  sink.send(&[throttled.clone(), Sample { value: 5.0, ..throttled }]).ok().unwrap();
+  let rendered = sink.render_at(sample().timestamp);
+  assert!(rendered.contains("# TYPE throttled_periods_total counter\n"));
+  assert!(rendered.contains("throttled_periods_total{container=\"ze-id\"} 5\n"));
+}
+
+#[test]
+fn serves_metrics_over_http() {
+  let mut sink = PrometheusSink::new();
+  let address = sink.serve("127.0.0.1:0").unwrap();
+  sink.send(&[Sample { timestamp: sample::now(), ..sample() }]).ok().unwrap();
+  let http = HttpClient::new();
+  let mut response = http.get(&format!("http://{}/metrics", address)).send().unwrap();
+  assert_eq!(200, response.status().as_u16());
+  assert!(response.text().unwrap().contains("max_usage_bytes{container=\"ze-id\"} 25"));
+  let response = http.get(&format!("http://{}/", address)).send().unwrap();
+  assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,60 @@
+use crate::metadata_v2::{Metric, Stats};
+use crate::rates::RateTracker;
+use chrono::DateTime;
+
+fn sample(timestamp: &str, rx_bytes: f64) -> Vec<Stats> {
+  vec![Stats {
+    container_id: "ze-id".to_owned(),
+    counters: vec![Metric {
+      name: "network_rx_bytes".to_owned(),
+      unit: "Bytes".to_owned(),
+      value: rx_bytes,
+    }],
+    cumulative: vec![],
+    metrics: vec![],
+    timestamp: DateTime::parse_from_rfc3339(timestamp).unwrap(),
+  }]
+}
+
+#[test]
+fn first_sample_yields_no_rate() {
+  let mut rates = RateTracker::new();
+  let mut stats = sample("2019-01-07T23:15:00Z", 1000.0);
+  rates.update(&mut stats);
+  assert_eq!(Vec::<Metric>::new(), stats[0].metrics);
+  assert_eq!(Vec::<Metric>::new(), stats[0].counters);
+}
+
+#[test]
+fn rate_between_samples() {
+  let mut rates = RateTracker::new();
+  rates.update(&mut sample("2019-01-07T23:15:00Z", 1000.0));
+  let mut stats = sample("2019-01-07T23:15:10Z", 3000.0);
+  rates.update(&mut stats);
+  assert_eq!(vec![Metric {
+    name: "network_rx_bytes".to_owned(),
+    unit: "Bytes/Second".to_owned(),
+    value: 200.0,
+  }], stats[0].metrics);
+}
+
+#[test]
+fn counter_reset_skips_one_sample() {
+  let mut rates = RateTracker::new();
+  rates.update(&mut sample("2019-01-07T23:15:00Z", 1000.0));
+  let mut reset = sample("2019-01-07T23:15:10Z", 50.0);
+  rates.update(&mut reset);
+  assert_eq!(Vec::<Metric>::new(), reset[0].metrics);
+  let mut stats = sample("2019-01-07T23:15:20Z", 150.0);
+  rates.update(&mut stats);
+  assert_eq!(Some(10.0), stats[0].metrics.first().map(|m| m.value));
+}
+
+#[test]
+fn repeated_sample_yields_no_rate() {
+  let mut rates = RateTracker::new();
+  rates.update(&mut sample("2019-01-07T23:15:00Z", 1000.0));
+  let mut stats = sample("2019-01-07T23:15:00Z", 1000.0);
+  rates.update(&mut stats);
+  assert_eq!(Vec::<Metric>::new(), stats[0].metrics);
+}
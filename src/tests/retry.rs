@@ -0,0 +1,99 @@
+use crate::retry::{self, Backoff, FailureTolerance, RetryBudget};
+use failure::{Error, format_err};
+use std::cell::Cell;
+use std::time::Duration;
+
+#[test]
+fn succeeds_without_retry() -> Result<(), Error> {
+  let attempts = Cell::new(0);
+  let result = retry::with_backoff(2, Duration::from_millis(1), || {
+    attempts.set(attempts.get() + 1);
+    Ok(17)
+  })?;
+  assert_eq!(17, result);
+  assert_eq!(1, attempts.get());
+  Ok(())
+}
+
+#[test]
+fn retries_until_success() -> Result<(), Error> {
+  let attempts = Cell::new(0);
+  let result = retry::with_backoff(2, Duration::from_millis(1), || {
+    attempts.set(attempts.get() + 1);
+    if attempts.get() < 3 { Err(format_err!("boom!")) } else { Ok(17) }
+  })?;
+  assert_eq!(17, result);
+  assert_eq!(3, attempts.get());
+  Ok(())
+}
+
+#[test]
+fn gives_up_after_retries() {
+  let attempts = Cell::new(0);
+  let result: Result<(), Error> = retry::with_backoff(2, Duration::from_millis(1), || {
+    attempts.set(attempts.get() + 1);
+    Err(format_err!("boom!"))
+  });
+  assert!(result.is_err());
+  assert_eq!(3, attempts.get());
+}
+
+#[test]
+fn tolerates_failures_below_limit() -> Result<(), Error> {
+  let mut tolerance = FailureTolerance::new(3);
+  assert_eq!(None, tolerance.check::<usize>(Err(format_err!("boom!")))?);
+  assert_eq!(None, tolerance.check::<usize>(Err(format_err!("boom!")))?);
+  assert!(tolerance.check::<usize>(Err(format_err!("boom!"))).is_err());
+  Ok(())
+}
+
+#[test]
+fn success_resets_tolerance() -> Result<(), Error> {
+  let mut tolerance = FailureTolerance::new(2);
+  assert_eq!(None, tolerance.check::<usize>(Err(format_err!("boom!")))?);
+  assert_eq!(Some(1), tolerance.check(Ok(1))?);
+  assert_eq!(None, tolerance.check::<usize>(Err(format_err!("boom!")))?);
+  Ok(())
+}
+
+#[test]
+fn zero_tolerates_forever() -> Result<(), Error> {
+  let mut tolerance = FailureTolerance::new(0);
+  for _ in 0..100 {
+    assert_eq!(None, tolerance.check::<usize>(Err(format_err!("boom!")))?);
+  }
+  Ok(())
+}
+
+fn backoff() -> Backoff {
+  Backoff { initial_delay: Duration::from_millis(100), max_delay: Duration::from_secs(1) }
+}
+
+#[test]
+fn backoff_ceiling_doubles_up_to_max() {
+  assert_eq!(Duration::from_millis(100), backoff().ceiling(0));
+  assert_eq!(Duration::from_millis(400), backoff().ceiling(2));
+  assert_eq!(Duration::from_secs(1), backoff().ceiling(4));
+  assert_eq!(Duration::from_secs(1), backoff().ceiling(100));
+}
+
+#[test]
+fn backoff_is_jittered_below_ceiling() {
+  assert_eq!(Duration::from_millis(0), backoff().jittered(1, 0.0));
+  assert_eq!(Duration::from_millis(100), backoff().jittered(1, 0.5));
+  assert_eq!(Duration::from_millis(200), backoff().jittered(1, 1.0));
+  for _ in 0..100 {
+    assert!(backoff().delay(3) <= Duration::from_millis(800));
+  }
+}
+
+#[test]
+fn budget_is_spent_and_capped() {
+  let mut budget = RetryBudget::new(2);
+  assert!(budget.withdraw(2.0));
+  assert!(!budget.withdraw(1.0));
+  budget.deposit(0.5);
+  assert!(!budget.withdraw(1.0));
+  budget.deposit(10.0);
+  assert_eq!(2.0, budget.tokens());
+}
@@ -0,0 +1,151 @@
+use crate::route::Route;
+use crate::sample::Sample;
+use crate::sink::{Action, Sink};
+use failure::format_err;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use super::sample;
+use super::sink::FakeSink;
+
+fn retry() -> Result<(), Action> {
+  Err(Action::Retry("later".to_owned()))
+}
+
+struct SlowSink;
+
+impl Sink for SlowSink {
+  fn name(&self) -> &str {
+    "slow"
+  }
+
+  fn batch_len(&self, pending: &[Sample]) -> usize {
+    pending.len()
+  }
+
+  fn send(&mut self, _: &[Sample]) -> Result<(), Action> {
+    sleep(Duration::from_millis(300));
+    Ok(())
+  }
+}
+
+#[test]
+fn flushes_queue_through_sink() {
+  let mut route = Route::new(Box::new(FakeSink::new(2, vec![])), 10, None);
+  route.push(&vec![sample(); 3]);
+  assert_eq!(3, route.flush().unwrap());
+  assert_eq!(0, route.queued());
+}
+
+#[test]
+fn batch_size_overrides_sink() {
+  let mut route = Route::new(Box::new(FakeSink::new(10, vec![Ok(()), retry()])), 10, Some(2));
+  route.push(&vec![sample(); 5]);
+  assert_eq!(2, route.flush().unwrap());
+  assert_eq!(3, route.queued());
+}
+
+#[test]
+fn culls_to_own_queue_size() {
+  let mut route = Route::new(Box::new(FakeSink::new(10, vec![retry()])), 2, None);
+  route.push(&vec![sample(); 5]);
+  assert_eq!(0, route.flush().unwrap());
+  assert_eq!(2, route.queued());
+}
+
+#[test]
+fn backs_off_after_repeated_failures() {
+  let mut route = Route::new(Box::new(FakeSink::new(10, vec![retry(), retry()])), 10, None);
+  route.push(&[sample()]);
+  assert_eq!(0, route.flush().unwrap());
+  assert_eq!(0, route.flush().unwrap());
+  route.push(&[sample()]);
+  assert_eq!(0, route.flush().unwrap());
+  assert_eq!(2, route.queued());
+  assert_eq!(2, route.flush().unwrap());
+}
+
+#[test]
+fn keeps_queue_size_while_backing_off() {
+  let mut route = Route::new(Box::new(FakeSink::new(10, vec![retry(), retry()])), 3, None);
+  route.push(&[sample()]);
+  route.flush().unwrap();
+  route.flush().unwrap();
+  route.push(&vec![sample(); 5]);
+  route.flush().unwrap();
+  assert_eq!(3, route.queued());
+}
+
+#[test]
+fn independent_routes() {
+  let mut broken = Route::new(Box::new(FakeSink::new(10, vec![retry()])), 10, None);
+  let mut working = Route::new(Box::new(FakeSink::new(10, vec![])), 10, None);
+  for route in [&mut broken, &mut working].iter_mut() {
+    route.push(&vec![sample(); 2]);
+    route.flush().unwrap();
+  }
+  assert_eq!(2, broken.queued());
+  assert_eq!(0, working.queued());
+}
+
+#[test]
+fn failure_is_reported() {
+  let mut route = Route::new(Box::new(FakeSink::new(10, vec![Err(Action::Fail(format_err!("boom!")))])), 10, None);
+  route.push(&[sample()]);
+  assert!(route.flush().is_err());
+}
+
+#[test]
+fn failure_keeps_queue_and_backs_off() {
+  let fail = || Err(Action::Fail(format_err!("boom!")));
+  let mut route = Route::new(Box::new(FakeSink::new(10, vec![fail(), fail()])), 3, None);
+  route.push(&vec![sample(); 2]);
+  assert!(route.flush().is_err());
+  assert!(route.flush().is_err());
+  route.push(&vec![sample(); 2]);
+  assert_eq!(0, route.flush().unwrap());
+  assert_eq!(3, route.queued());
+  assert_eq!(3, route.flush().unwrap());
+}
+
+#[test]
+fn failure_drops_acknowledged_batches_from_queue() {
+  let mut route = Route::new(Box::new(FakeSink::new(2, vec![Ok(()), Err(Action::Fail(format_err!("boom!")))])), 10, None);
+  route.push(&vec![sample(); 5]);
+  assert!(route.flush().is_err());
+  assert_eq!(3, route.queued());
+  assert_eq!(3, route.flush().unwrap());
+}
+
+#[test]
+fn flushes_until_deadline() {
+  let mut route = Route::new(Box::new(FakeSink::new(1, vec![retry()])), 10, None);
+  route.push(&vec![sample(); 2]);
+  assert!(route.flush_until(Instant::now() + Duration::from_secs(2)));
+  assert_eq!(0, route.queued());
+}
+
+#[test]
+fn invalid_samples_are_not_enqueued() {
+  let mut route = Route::new(Box::new(FakeSink::new(10, vec![])), 10, None);
+  route.push(&[sample(), Sample { value: f64::NAN, ..sample() }]);
+  assert_eq!(1, route.queued());
+}
+
+#[test]
+fn worker_does_not_block_on_slow_sink() {
+  let worker = Route::new(Box::new(SlowSink), 10, None).spawn().unwrap();
+  let start = Instant::now();
+  worker.report(&[sample()], 1);
+  worker.report(&[sample()], 1);
+  assert!(start.elapsed() < Duration::from_millis(300));
+  worker.shutdown(&[sample()], Instant::now() + Duration::from_secs(5));
+  assert!(worker.join());
+}
+
+#[test]
+fn worker_reports_failed_final_flush() {
+  let fail = Err(Action::Fail(format_err!("boom!")));
+  let worker = Route::new(Box::new(FakeSink::new(10, vec![fail])), 10, None).spawn().unwrap();
+  worker.shutdown(&[sample()], Instant::now() + Duration::from_secs(5));
+  assert!(!worker.join());
+}
@@ -0,0 +1,91 @@
+use crate::shutdown::{self, Shutdown};
+use failure::format_err;
+use crate::sample::Sample;
+use std::cmp::min;
+use std::time::{Duration, Instant};
+use super::sample;
+
+#[test]
+fn sleeps_full_duration_without_request() {
+  let shutdown = Shutdown::new();
+  let start = Instant::now();
+  assert!(!shutdown.sleep(Duration::from_millis(20)));
+  assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn sleep_is_interrupted_by_request() {
+  let shutdown = Shutdown::new();
+  shutdown.request();
+  let start = Instant::now();
+  assert!(shutdown.sleep(Duration::from_secs(60)));
+  assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn flushes_whole_queue_in_chunks() {
+  let mut queue = vec![sample(), sample(), sample()];
+  let deadline = Instant::now() + Duration::from_secs(5);
+  assert!(shutdown::flush_until(deadline, &mut queue, |queue: &Vec<Sample>| (min(2, queue.len()), None)));
+  assert!(queue.is_empty());
+}
+
+#[test]
+fn flush_fails_at_deadline() {
+  let mut queue = vec![sample()];
+  let deadline = Instant::now() + Duration::from_millis(50);
+  assert!(!shutdown::flush_until(deadline, &mut queue, |_: &Vec<Sample>| (0, None)));
+  assert_eq!(1, queue.len());
+}
+
+#[test]
+fn flush_fails_on_error() {
+  let mut queue = vec![sample()];
+  let deadline = Instant::now() + Duration::from_secs(5);
+  assert!(!shutdown::flush_until(deadline, &mut queue, |_: &Vec<Sample>| (0, Some(format_err!("boom!")))));
+  assert_eq!(1, queue.len());
+}
+
+#[test]
+fn flush_keeps_acknowledged_samples_out_of_queue_on_error() {
+  let mut queue = vec![sample(), sample(), sample()];
+  let deadline = Instant::now() + Duration::from_secs(5);
+  assert!(!shutdown::flush_until(deadline, &mut queue, |_: &Vec<Sample>| (2, Some(format_err!("boom!")))));
+  assert_eq!(1, queue.len());
+}
+
+#[test]
+fn empty_queue_is_flushed() {
+  let mut queue = Vec::<Sample>::new();
+  assert!(shutdown::flush_until(Instant::now(), &mut queue, |_: &Vec<Sample>| (0, None)));
+}
+
+#[test]
+fn next_tick_keeps_cadence() {
+  let start = Instant::now();
+  let period = Duration::from_secs(5);
+  assert_eq!(start + period, shutdown::next_tick(start, period, start + Duration::from_millis(1200)));
+}
+
+#[test]
+fn next_tick_skips_missed_ticks() {
+  let start = Instant::now();
+  let period = Duration::from_secs(5);
+  assert_eq!(start + period * 3, shutdown::next_tick(start, period, start + Duration::from_millis(12_000)));
+  assert_eq!(start + period * 2, shutdown::next_tick(start, period, start + period));
+}
+
+#[test]
+fn next_tick_with_zero_period_is_now() {
+  let start = Instant::now();
+  let now = start + Duration::from_secs(1);
+  assert_eq!(now, shutdown::next_tick(start, Duration::from_secs(0), now));
+}
+
+#[test]
+fn sleep_until_past_deadline_returns_immediately() {
+  let shutdown = Shutdown::new();
+  let start = Instant::now();
+  assert!(!shutdown.sleep_until(start));
+  assert!(start.elapsed() < Duration::from_secs(1));
+}
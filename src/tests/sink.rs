@@ -0,0 +1,166 @@
+use crate::sample::Sample;
+use crate::sink::{self, Action, Sink};
+use failure::format_err;
+use reqwest::StatusCode;
+use std::time::Instant;
+use super::sample;
+
+pub struct FakeSink {
+  batch_size: usize,
+  pub batches: Vec<usize>,
+  outcomes: Vec<Result<(), Action>>,
+}
+
+impl FakeSink {
+  pub fn new(batch_size: usize, outcomes: Vec<Result<(), Action>>) -> FakeSink {
+    FakeSink { batch_size, batches: Vec::new(), outcomes }
+  }
+}
+
+impl Sink for FakeSink {
+  fn name(&self) -> &str {
+    "fake"
+  }
+
+  fn batch_len(&self, _: &[Sample]) -> usize {
+    self.batch_size
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action> {
+    self.batches.push(batch.len());
+    if self.outcomes.is_empty() { Ok(()) } else { self.outcomes.remove(0) }
+  }
+}
+
+#[test]
+fn delivers_in_batches() {
+  let mut fake = FakeSink::new(2, vec![]);
+  let queue = vec![sample(); 5];
+  assert_eq!(5, sink::deliver(&mut fake, &queue).unwrap());
+  assert_eq!(vec![2, 2, 1], fake.batches);
+}
+
+#[test]
+fn stops_on_retry() {
+  let mut fake = FakeSink::new(2, vec![Ok(()), Err(Action::Retry("later".to_owned()))]);
+  let queue = vec![sample(); 5];
+  assert_eq!(2, sink::deliver(&mut fake, &queue).unwrap());
+  assert_eq!(vec![2, 2], fake.batches);
+}
+
+#[test]
+fn fails_on_failure() {
+  let mut fake = FakeSink::new(2, vec![Err(Action::Fail(format_err!("boom!")))]);
+  let queue = vec![sample(); 5];
+  assert!(sink::deliver(&mut fake, &queue).is_err());
+}
+
+#[test]
+fn failure_reports_acknowledged_batches() {
+  let mut fake = FakeSink::new(2, vec![Ok(()), Err(Action::Fail(format_err!("boom!")))]);
+  let queue = vec![sample(); 5];
+  let (acknowledged, failure) = sink::deliver_in_batches(&mut fake, &queue, usize::MAX, None);
+  assert_eq!(2, acknowledged);
+  assert!(failure.is_some());
+}
+
+#[test]
+fn always_makes_progress() {
+  let mut fake = FakeSink::new(0, vec![]);
+  let queue = vec![sample(); 2];
+  assert_eq!(2, sink::deliver(&mut fake, &queue).unwrap());
+  assert_eq!(vec![1, 1], fake.batches);
+}
+
+#[test]
+fn empty_queue_sends_nothing() {
+  let mut fake = FakeSink::new(2, vec![]);
+  assert_eq!(0, sink::deliver(&mut fake, &[]).unwrap());
+  assert!(fake.batches.is_empty());
+}
+
+#[test]
+fn batch_size_caps_sink_batches() {
+  let mut fake = FakeSink::new(3, vec![]);
+  let queue = vec![sample(); 5];
+  assert_eq!(5, sink::deliver_in_batches(&mut fake, &queue, 2, None).0);
+  assert_eq!(vec![2, 2, 1], fake.batches);
+}
+
+#[test]
+fn stops_at_deadline() {
+  let mut fake = FakeSink::new(2, vec![]);
+  let queue = vec![sample(); 5];
+  assert_eq!(0, sink::deliver_in_batches(&mut fake, &queue, usize::MAX, Some(Instant::now())).0);
+  assert!(fake.batches.is_empty());
+}
+
+struct PoisonSink {
+  batches: Vec<usize>,
+}
+
+impl Sink for PoisonSink {
+  fn name(&self) -> &str {
+    "poison"
+  }
+
+  fn batch_len(&self, pending: &[Sample]) -> usize {
+    pending.len()
+  }
+
+  fn send(&mut self, batch: &[Sample]) -> Result<(), Action> {
+    self.batches.push(batch.len());
+    if batch.iter().any(|s| s.value < 0.0) { Err(Action::Reject("negative".to_owned())) } else { Ok(()) }
+  }
+}
+
+#[test]
+fn bisects_to_drop_rejected_samples() {
+  let mut poison = PoisonSink { batches: Vec::new() };
+  let mut queue = vec![sample(); 8];
+  queue[5].value = -1.0;
+  assert_eq!(8, sink::deliver(&mut poison, &queue).unwrap());
+  assert_eq!(vec![8, 4, 4, 2, 1, 1, 2], poison.batches);
+}
+
+#[test]
+fn drops_rejected_single_sample() {
+  let mut poison = PoisonSink { batches: Vec::new() };
+  let queue = vec![Sample { value: -1.0, ..sample() }];
+  assert_eq!(1, sink::deliver(&mut poison, &queue).unwrap());
+}
+
+#[test]
+fn validation_rejects_non_finite_values_by_default() {
+  let fake = FakeSink::new(1, vec![]);
+  assert!(fake.validate(&sample()).is_ok());
+  assert!(fake.validate(&Sample { value: f64::INFINITY, ..sample() }).is_err());
+}
+
+#[test]
+fn classifies_responses() {
+  assert!(sink::classify_http_response("some-service", StatusCode::NO_CONTENT, "").is_ok());
+  match sink::classify_http_response("some-service", StatusCode::SERVICE_UNAVAILABLE, "busy") {
+    Err(Action::Retry(_)) => (),
+    _ => panic!("Expected retry"),
+  }
+  match sink::classify_http_response("some-service", StatusCode::TOO_MANY_REQUESTS, "slow down") {
+    Err(Action::Retry(_)) => (),
+    _ => panic!("Expected retry"),
+  }
+  match sink::classify_http_response("some-service", StatusCode::BAD_REQUEST, "bad line") {
+    Err(Action::Reject(_)) => (),
+    _ => panic!("Expected rejection"),
+  }
+  match sink::classify_http_response("some-service", StatusCode::UNAUTHORIZED, "bad token") {
+    Err(Action::Fail(_)) => (),
+    _ => panic!("Expected failure"),
+  }
+}
+
+#[test]
+fn sink_kind_names_parse_back() {
+  for name in &["cloudwatch", "prometheus", "statsd", "dogstatsd", "emf", "influxdb", "otlp"] {
+    assert_eq!(*name, name.parse::<sink::SinkKind>().unwrap().name());
+  }
+}
@@ -0,0 +1,106 @@
+use chrono::Duration as ChronoDuration;
+use crate::route::Route;
+use crate::sample::{self, Kind, Sample, Statistics};
+use crate::sink::Action;
+use crate::spool::{Spool, SpoolLimits};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use super::sample;
+use super::sink::FakeSink;
+
+fn spool_dir(test: &str) -> PathBuf {
+  let dir = env::temp_dir().join(format!("fargate-stats-reporter-{}-{}", std::process::id(), test));
+  let _ = fs::remove_dir_all(&dir);
+  dir
+}
+
+fn recent() -> Sample {
+  Sample { timestamp: sample::now(), ..sample() }
+}
+
+fn retry() -> Result<(), Action> {
+  Err(Action::Retry("later".to_owned()))
+}
+
+#[test]
+fn replays_what_was_appended() {
+  let dir = spool_dir("replay");
+  let statistics = Statistics { sample_count: 2.0, sum: 3.0, minimum: 1.0, maximum: 2.0 };
+  let samples = vec![
+    recent(),
+    Sample { kind: Kind::Sum, value: 3.5, ..recent() },
+    Sample { statistics: Some(statistics), value: 1.5, ..recent() },
+  ];
+  let (mut spool, replayed) = Spool::open(&dir, "cloudwatch", SpoolLimits::default()).unwrap();
+  assert!(replayed.is_empty());
+  spool.append(&samples).unwrap();
+  let (_, replayed) = Spool::open(&dir, "cloudwatch", SpoolLimits::default()).unwrap();
+  assert_eq!(samples, replayed);
+}
+
+#[test]
+fn skips_corrupt_entries() {
+  let dir = spool_dir("corrupt");
+  let (mut spool, _) = Spool::open(&dir, "cloudwatch", SpoolLimits::default()).unwrap();
+  spool.append(&[recent(), recent()]).unwrap();
+  let contents = fs::read_to_string(spool.path()).unwrap();
+  let damaged = contents.replacen("max_usage", "max_uzage", 1) + "0123 {\"truncated";
+  fs::write(spool.path(), damaged).unwrap();
+  let (_, replayed) = Spool::open(&dir, "cloudwatch", SpoolLimits::default()).unwrap();
+  assert_eq!(vec![recent().name], replayed.iter().map(|s| s.name.clone()).collect::<Vec<_>>());
+}
+
+#[test]
+fn drops_samples_older_than_max_age() {
+  let dir = spool_dir("age");
+  let limits = SpoolLimits { max_age: Duration::from_secs(60), ..Default::default() };
+  let (mut spool, _) = Spool::open(&dir, "cloudwatch", limits.clone()).unwrap();
+  let stale = Sample { timestamp: sample::now() - ChronoDuration::seconds(120), ..sample() };
+  spool.append(&[stale, recent()]).unwrap();
+  let (_, replayed) = Spool::open(&dir, "cloudwatch", limits).unwrap();
+  assert_eq!(1, replayed.len());
+}
+
+#[test]
+fn keeps_newest_samples_within_max_bytes() {
+  let dir = spool_dir("size");
+  let (mut spool, _) = Spool::open(&dir, "cloudwatch", SpoolLimits::default()).unwrap();
+  let samples: Vec<Sample> = (0..10).map(|i| Sample { value: f64::from(i), ..recent() }).collect();
+  spool.append(&samples).unwrap();
+  let line = fs::metadata(spool.path()).unwrap().len() / 10;
+  let limits = SpoolLimits { max_bytes: line * 3, ..Default::default() };
+  let (_, replayed) = Spool::open(&dir, "cloudwatch", limits).unwrap();
+  assert_eq!(vec![7.0, 8.0, 9.0], replayed.iter().map(|s| s.value).collect::<Vec<_>>());
+}
+
+#[test]
+fn route_removes_acknowledged_samples_from_spool() {
+  let dir = spool_dir("ack");
+  let mut route = Route::new(Box::new(FakeSink::new(2, vec![Ok(()), retry()])), 10, None)
+    .with_spool(&dir, "fake", SpoolLimits::default())
+    .unwrap();
+  route.push(&vec![recent(); 5]);
+  assert_eq!(2, route.flush().unwrap());
+  let (_, replayed) = Spool::open(&dir, "fake", SpoolLimits::default()).unwrap();
+  assert_eq!(3, replayed.len());
+}
+
+#[test]
+fn route_replays_spool_after_restart() {
+  let dir = spool_dir("restart");
+  let mut route = Route::new(Box::new(FakeSink::new(10, vec![retry()])), 10, None)
+    .with_spool(&dir, "fake", SpoolLimits::default())
+    .unwrap();
+  route.push(&vec![recent(); 4]);
+  assert_eq!(0, route.flush().unwrap());
+  drop(route);
+  let mut restarted = Route::new(Box::new(FakeSink::new(10, vec![])), 10, None)
+    .with_spool(&dir, "fake", SpoolLimits::default())
+    .unwrap();
+  assert_eq!(4, restarted.queued());
+  assert_eq!(4, restarted.flush().unwrap());
+  let (_, replayed) = Spool::open(&dir, "fake", SpoolLimits::default()).unwrap();
+  assert!(replayed.is_empty());
+}
@@ -0,0 +1,88 @@
+use crate::sample::{Dimension, Sample};
+use crate::sink::{self, Sink};
+use crate::statsd::{self, Flavor, StatsdSink};
+use std::net::UdpSocket;
+use std::time::Duration;
+use super::sample;
+
+fn two_dimensions() -> Sample {
+  Sample {
+    dimensions: vec![
+      Dimension { name: "task".to_owned(), value: "app".to_owned() },
+      Dimension { name: "image".to_owned(), value: "repo/app:1.0".to_owned() },
+    ],
+    ..sample()
+  }
+}
+
+fn agent() -> (UdpSocket, String) {
+  let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+  agent.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+  let address = agent.local_addr().unwrap().to_string();
+  (agent, address)
+}
+
+fn receive(agent: &UdpSocket) -> String {
+  let mut buffer = [0; 2048];
+  let len = agent.recv(&mut buffer).unwrap();
+  String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+#[test]
+fn statsd_line_puts_dimensions_in_path() {
+  assert_eq!("ecs.app.repo/app_1_0.max_usage:25|g", statsd::format_line(&two_dimensions(), Flavor::StatsD, "ecs."));
+}
+
+#[test]
+fn dogstatsd_line_has_tags() {
+  assert_eq!(
+    "ecs.max_usage:25|g|#task:app,image:repo/app:1.0",
+    statsd::format_line(&two_dimensions(), Flavor::DogStatsD, "ecs")
+  );
+}
+
+#[test]
+fn line_without_prefix_or_dimensions() {
+  let sample = Sample { dimensions: vec![], value: 0.5, ..sample() };
+  assert_eq!("max_usage:0.5|g", statsd::format_line(&sample, Flavor::DogStatsD, ""));
+}
+
+#[test]
+fn negative_statsd_gauge_is_reset_first() {
+  let sample = Sample { dimensions: vec![], value: -2.5, ..sample() };
+  assert_eq!("max_usage:0|g\nmax_usage:-2.5|g", statsd::format_line(&sample, Flavor::StatsD, ""));
+  assert_eq!("max_usage:-2.5|g", statsd::format_line(&sample, Flavor::DogStatsD, ""));
+}
+
+#[test]
+fn negative_zero_is_not_a_decrement() {
+  let sample = Sample { dimensions: vec![], value: -0.0, ..sample() };
+  assert_eq!("max_usage:0|g", statsd::format_line(&sample, Flavor::StatsD, ""));
+}
+
+#[test]
+fn batches_fit_in_packet() {
+  let (_agent, address) = agent();
+  let line_len = statsd::format_line(&sample(), Flavor::StatsD, "").len();
+  let sink = StatsdSink::new(&address, Flavor::StatsD, "", line_len * 2 + 1).unwrap();
+  assert_eq!(2, sink.batch_len(&vec![sample(); 5]));
+  let sink = StatsdSink::new(&address, Flavor::StatsD, "", 1).unwrap();
+  assert_eq!(1, sink.batch_len(&vec![sample(); 5]));
+}
+
+#[test]
+fn sends_gauges_over_udp() {
+  let (agent, address) = agent();
+  let line = statsd::format_line(&sample(), Flavor::DogStatsD, "ecs");
+  let mut sink = StatsdSink::new(&address, Flavor::DogStatsD, "ecs", line.len() * 2 + 1).unwrap();
+  assert_eq!(3, sink::deliver(&mut sink, &vec![sample(); 3]).unwrap());
+  assert_eq!(format!("{}\n{}", line, line), receive(&agent));
+  assert_eq!(line, receive(&agent));
+}
+
+#[test]
+fn flavors_have_distinct_names() {
+  let statsd = StatsdSink::new("127.0.0.1:8125", Flavor::StatsD, "", 1432).unwrap();
+  let dogstatsd = StatsdSink::new("127.0.0.1:8125", Flavor::DogStatsD, "", 1432).unwrap();
+  assert_eq!(("statsd", "dogstatsd"), (statsd.name(), dogstatsd.name()));
+}
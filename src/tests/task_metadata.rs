@@ -1,7 +1,8 @@
+use crate::dimension_spec::{self, DimensionSpec};
 use crate::metadata_v2;
+use crate::sample::Dimension;
 use mockito::mock;
 use reqwest::Client as HttpClient;
-use rusoto_cloudwatch::Dimension;
 use serde_json::json;
 use std::collections::HashMap;
 
@@ -18,7 +19,7 @@ fn no_containers() {
 
   assert_eq!(
     HashMap::<String, metadata_v2::Metadata>::new(),
-    metadata_v2::task_metadata(&http, &mockito::server_url()).unwrap()
+    metadata_v2::task_metadata(&http, &mockito::server_url(), &dimension_spec::default_specs()).unwrap()
   );
 }
 
@@ -43,19 +44,96 @@ fn with_containers() {
     .with_body(reply.to_string())
     .create();
 
-  let actual = metadata_v2::task_metadata(&http, &mockito::server_url()).unwrap();
+  let actual = metadata_v2::task_metadata(&http, &mockito::server_url(), &dimension_spec::default_specs()).unwrap();
   let mut expected = HashMap::<String, metadata_v2::Metadata>::new();
   expected.insert(
     "ze-id".to_owned(),
     metadata_v2::Metadata {
       container_id: "ze-id".to_owned(),
+      cpu_limit: None,
       dimensions: vec![
         Dimension {
           name: "task".to_owned(),
           value: "some-container".to_owned()
         }
+      ],
+      resource: vec![
+        Dimension {
+          name: "container.id".to_owned(),
+          value: "ze-id".to_owned()
+        },
+        Dimension {
+          name: "container.name".to_owned(),
+          value: "some-container".to_owned()
+        }
       ]
     }
   );
   assert_eq!(expected, actual);
 }
+
+#[test]
+fn configured_dimensions() {
+  let body = json!({
+    "Cluster": "arn:aws:ecs:eu-west-1:111122223333:cluster/etl",
+    "TaskARN": "arn:aws:ecs:eu-west-1:111122223333:task/etl/158d1c8083dd49d6b527399fd6414f5c",
+    "Family": "nightly-load",
+    "Revision": "26",
+    "Containers": [
+      {
+        "DockerId": "ze-id",
+        "Name": "some-container",
+        "Image": "example/loader:1.2",
+        "Labels": {"team": "data"}
+      }
+    ]
+  });
+  let specs: Vec<DimensionSpec> = [
+    "cluster=ClusterName", "family=Family", "revision=Revision", "task_id=TaskId",
+    "image=Image", "team=container:/Labels/team",
+  ].iter().map(|s| s.parse().unwrap()).collect();
+
+  let actual = metadata_v2::parse_task_metadata(&body, &specs).unwrap();
+  let dimensions: Vec<(&str, &str)> = actual["ze-id"].dimensions.iter()
+    .map(|d| (d.name.as_str(), d.value.as_str()))
+    .collect();
+  assert_eq!(vec![
+    ("cluster", "etl"),
+    ("family", "nightly-load"),
+    ("revision", "26"),
+    ("task_id", "158d1c8083dd49d6b527399fd6414f5c"),
+    ("image", "example/loader:1.2"),
+    ("team", "data"),
+  ], dimensions);
+}
+
+#[test]
+fn missing_dimension_is_skipped() {
+  let body = json!({"Containers": [{"DockerId": "ze-id", "Name": "some-container"}]});
+  let specs: Vec<DimensionSpec> = vec!["family=Family".parse().unwrap()];
+  let actual = metadata_v2::parse_task_metadata(&body, &specs).unwrap();
+  assert_eq!(Vec::<Dimension>::new(), actual["ze-id"].dimensions);
+}
+
+#[test]
+fn containers_is_not_an_array() {
+  let body = json!({"Containers": {"DockerId": "ze-id"}});
+  match metadata_v2::parse_task_metadata(&body, &dimension_spec::default_specs()) {
+    Err(metadata_v2::ParseError::UnexpectedType { field, .. }) => assert_eq!("Containers", field),
+    other => panic!("Expected UnexpectedType, got {:?}", other),
+  }
+}
+
+#[test]
+fn malformed_containers_are_skipped() {
+  let body = json!({"Containers": [
+    {"DockerId": "no-name"},
+    {"DockerId": "numeric-name", "Name": 17},
+    {"Name": "no-id"},
+    {"DockerId": 42, "Name": "numeric-id"},
+    "not-a-container",
+    {"DockerId": "ze-id", "Name": "some-container"},
+  ]});
+  let actual = metadata_v2::parse_task_metadata(&body, &dimension_spec::default_specs()).unwrap();
+  assert_eq!(vec!["ze-id"], actual.keys().collect::<Vec<&String>>());
+}